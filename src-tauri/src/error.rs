@@ -0,0 +1,120 @@
+use serde::Serialize;
+use std::fmt;
+use std::io;
+use std::sync::PoisonError;
+
+/// Broad category the frontend can branch on without parsing messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    NotFound,
+    Io,
+    Parse,
+    InvalidInput,
+    /// Raised by writes that would clobber a newer version.
+    Conflict,
+    /// Broken invariants such as a poisoned lock; not the user's fault.
+    Internal,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AppError {
+    pub kind: ErrorKind,
+    pub message: String,
+    /// What the error is about, e.g. a note id or a file path.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+}
+
+pub type AppResult<T> = Result<T, AppError>;
+
+impl AppError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            context: None,
+        }
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::NotFound, message)
+    }
+
+    pub fn io(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Io, message)
+    }
+
+    pub fn parse(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Parse, message)
+    }
+
+    pub fn invalid_input(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::InvalidInput, message)
+    }
+
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Conflict, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Internal, message)
+    }
+
+    /// Keeps the innermost context, which is usually the most specific one.
+    pub fn with_context(self, context: impl fmt::Display) -> Self {
+        Self {
+            context: self.context.or_else(|| Some(context.to_string())),
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.context {
+            Some(context) => write!(f, "{} ({})", self.message, context),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<io::Error> for AppError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::NotFound => Self::not_found(e.to_string()),
+            _ => Self::io(e.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::parse(e.to_string())
+    }
+}
+
+impl From<serde_yaml::Error> for AppError {
+    fn from(e: serde_yaml::Error) -> Self {
+        Self::parse(e.to_string())
+    }
+}
+
+impl<T> From<PoisonError<T>> for AppError {
+    fn from(e: PoisonError<T>) -> Self {
+        Self::internal(e.to_string())
+    }
+}
+
+/// Attaches context to any error that converts into an `AppError`.
+pub trait ResultExt<T> {
+    fn context(self, context: impl fmt::Display) -> AppResult<T>;
+}
+
+impl<T, E: Into<AppError>> ResultExt<T> for Result<T, E> {
+    fn context(self, context: impl fmt::Display) -> AppResult<T> {
+        self.map_err(|e| e.into().with_context(context))
+    }
+}
@@ -1,12 +1,22 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
+mod error;
 mod notes;
 mod pomodoro;
+mod settings;
+mod vaults;
 
-use notes::{model::Note, NotesState};
-use pomodoro::commands::{to_seconds, PomodoroState};
+use error::AppResult;
+use notes::checkbox_parser::StateMark;
+use notes::due::DueWindow;
+use notes::listing::NoteQuery;
+use notes::model::{Note, NotePage};
+use notes::open_items::{ItemFilter, OpenItem};
+use notes::query::SavedQuery;
 use pomodoro::TimerState;
+use settings::SettingsState;
 use std::sync::Mutex;
-use tauri::State;
+use tauri::{Manager, State};
+use vaults::VaultsState;
 
 #[tauri::command]
 fn greet(name: &str) -> String {
@@ -14,18 +24,138 @@ fn greet(name: &str) -> String {
 }
 
 #[tauri::command(rename_all = "snake_case")]
-fn create_note(title: String, content: String, state: State<NotesState>) -> Result<Note, String> {
-    notes::commands::create_note(title, content, state)
+fn create_note(title: String, content: String, state: State<VaultsState>) -> AppResult<Note> {
+    notes::commands::create_note(title, content, &*state.active()?)
 }
 
 #[tauri::command(rename_all = "snake_case")]
-fn get_notes(state: State<NotesState>) -> Result<Vec<notes::model::NotePreview>, String> {
-    notes::commands::get_notes(state)
+fn get_notes(
+    query: Option<NoteQuery>,
+    state: State<VaultsState>,
+    settings: State<SettingsState>,
+) -> AppResult<NotePage> {
+    notes::commands::get_notes(
+        query.unwrap_or_default(),
+        &settings::checkbox_states(&settings)?,
+        &*state.active()?,
+    )
 }
 
 #[tauri::command(rename_all = "snake_case")]
-fn get_note(id: String, state: State<NotesState>) -> Result<Note, String> {
-    notes::commands::get_note(id, state)
+fn get_overdue_notes(
+    state: State<VaultsState>,
+    settings: State<SettingsState>,
+) -> AppResult<Vec<notes::model::NotePreview>> {
+    notes::commands::get_due_notes(
+        DueWindow::Overdue,
+        &settings::checkbox_states(&settings)?,
+        &*state.active()?,
+    )
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn get_notes_due_today(
+    state: State<VaultsState>,
+    settings: State<SettingsState>,
+) -> AppResult<Vec<notes::model::NotePreview>> {
+    notes::commands::get_due_notes(
+        DueWindow::Today,
+        &settings::checkbox_states(&settings)?,
+        &*state.active()?,
+    )
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn get_upcoming_notes(
+    state: State<VaultsState>,
+    settings: State<SettingsState>,
+) -> AppResult<Vec<notes::model::NotePreview>> {
+    notes::commands::get_due_notes(
+        DueWindow::Upcoming,
+        &settings::checkbox_states(&settings)?,
+        &*state.active()?,
+    )
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn set_note_schedule(
+    id: String,
+    due: Option<String>,
+    scheduled: Option<String>,
+    reminders: Vec<u64>,
+    expected_revision: Option<u64>,
+    state: State<VaultsState>,
+) -> AppResult<Note> {
+    notes::commands::set_note_schedule(
+        id,
+        due,
+        scheduled,
+        reminders,
+        expected_revision,
+        &*state.active()?,
+    )
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn set_task_status(
+    id: String,
+    is_task: bool,
+    is_done: bool,
+    expected_revision: Option<u64>,
+    state: State<VaultsState>,
+) -> AppResult<notes::model::TaskStatus> {
+    notes::commands::set_task_status(id, is_task, is_done, expected_revision, &*state.active()?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn set_note_recurrence(
+    id: String,
+    recurrence: Option<String>,
+    reset_checkboxes: bool,
+    expected_revision: Option<u64>,
+    state: State<VaultsState>,
+) -> AppResult<Note> {
+    notes::commands::set_note_recurrence(
+        id,
+        recurrence,
+        reset_checkboxes,
+        expected_revision,
+        &*state.active()?,
+    )
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn list_tags(state: State<VaultsState>) -> AppResult<Vec<notes::tags::TagCount>> {
+    notes::commands::list_tags(&*state.active()?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn rename_tag(
+    from: String,
+    to: String,
+    state: State<VaultsState>,
+) -> AppResult<Vec<notes::tags::TagCount>> {
+    notes::commands::rename_tag(from, to, &*state.active()?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn search_notes(
+    query: String,
+    limit: Option<usize>,
+    state: State<VaultsState>,
+    settings: State<SettingsState>,
+) -> AppResult<Vec<notes::search::SearchHit>> {
+    notes::commands::search_notes(
+        query,
+        limit,
+        &settings::checkbox_states(&settings)?,
+        &*state.active()?,
+    )
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn get_note(id: String, state: State<VaultsState>) -> AppResult<Note> {
+    notes::commands::get_note(id, &*state.active()?)
 }
 
 #[tauri::command(rename_all = "snake_case")]
@@ -33,65 +163,334 @@ fn update_note(
     id: String,
     title: String,
     content: String,
-    state: State<NotesState>,
-) -> Result<Note, String> {
-    notes::commands::update_note(id, title, content, state)
+    expected_revision: Option<u64>,
+    state: State<VaultsState>,
+    settings: State<SettingsState>,
+) -> AppResult<Note> {
+    notes::commands::update_note(
+        id,
+        title,
+        content,
+        expected_revision,
+        &settings::checkbox_states(&settings)?,
+        settings::auto_complete_tasks(&settings)?,
+        &*state.active()?,
+    )
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn create_child_note(
+    parent_id: String,
+    title: String,
+    content: String,
+    state: State<VaultsState>,
+) -> AppResult<Note> {
+    notes::commands::create_child_note(parent_id, title, content, &*state.active()?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn move_note(
+    id: String,
+    new_parent_id: Option<String>,
+    index: Option<usize>,
+    state: State<VaultsState>,
+) -> AppResult<Note> {
+    notes::commands::move_note(id, new_parent_id, index, &*state.active()?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn delete_note(id: String, state: State<VaultsState>) -> AppResult<()> {
+    notes::commands::delete_note(id, &*state.active()?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn load_all_notes(state: State<VaultsState>) -> AppResult<Vec<Note>> {
+    notes::commands::load_all_notes(&*state.active()?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn get_unreadable_notes(
+    state: State<VaultsState>,
+) -> AppResult<Vec<notes::storage::UnreadableNote>> {
+    notes::commands::get_unreadable_notes(&*state.active()?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn get_scan_failures(state: State<VaultsState>) -> AppResult<Vec<notes::storage::ScanFailure>> {
+    notes::commands::get_scan_failures(&*state.active()?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn parse_checkboxes(
+    content: String,
+    settings: State<SettingsState>,
+) -> AppResult<Vec<notes::checkbox_parser::Checkbox>> {
+    notes::commands::parse_checkboxes(content, &settings::checkbox_states(&settings)?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn get_checkbox_states(settings: State<SettingsState>) -> AppResult<Vec<StateMark>> {
+    notes::commands::get_checkbox_states(&settings)
 }
 
 #[tauri::command(rename_all = "snake_case")]
-fn delete_note(id: String, state: State<NotesState>) -> Result<(), String> {
-    notes::commands::delete_note(id, state)
+fn get_auto_complete_tasks(settings: State<SettingsState>) -> AppResult<bool> {
+    notes::commands::get_auto_complete_tasks(&settings)
 }
 
 #[tauri::command(rename_all = "snake_case")]
-fn load_all_notes(state: State<NotesState>) -> Result<Vec<Note>, String> {
-    notes::commands::load_all_notes(state)
+fn set_auto_complete_tasks(enabled: bool, settings: State<SettingsState>) -> AppResult<bool> {
+    notes::commands::set_auto_complete_tasks(enabled, &settings)
 }
 
 #[tauri::command(rename_all = "snake_case")]
-fn parse_checkboxes(content: String) -> Result<Vec<notes::checkbox_parser::Checkbox>, String> {
-    notes::commands::parse_checkboxes(content)
+fn update_checkbox_states(
+    states: Vec<StateMark>,
+    settings: State<SettingsState>,
+) -> AppResult<Vec<StateMark>> {
+    notes::commands::update_checkbox_states(states, &settings)
 }
 
 #[tauri::command(rename_all = "snake_case")]
 fn update_note_checkbox_status(
     note_id: String,
-    checkbox_text: String,
+    line: usize,
     new_status: bool,
-    state: State<NotesState>,
-) -> Result<Note, String> {
-    notes::commands::update_note_checkbox_status(note_id, checkbox_text, new_status, state)
+    expected_revision: Option<u64>,
+    state: State<VaultsState>,
+    settings: State<SettingsState>,
+) -> AppResult<Note> {
+    notes::commands::update_note_checkbox_status(
+        note_id,
+        line,
+        new_status,
+        expected_revision,
+        &settings::checkbox_states(&settings)?,
+        settings::auto_complete_tasks(&settings)?,
+        &*state.active()?,
+    )
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn promote_checkbox(
+    note_id: String,
+    line: usize,
+    expected_revision: Option<u64>,
+    state: State<VaultsState>,
+    settings: State<SettingsState>,
+) -> AppResult<Note> {
+    notes::commands::promote_checkbox(
+        note_id,
+        line,
+        expected_revision,
+        &settings::checkbox_states(&settings)?,
+        &*state.active()?,
+    )
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn demote_note(
+    id: String,
+    expected_revision: Option<u64>,
+    state: State<VaultsState>,
+    settings: State<SettingsState>,
+) -> AppResult<Note> {
+    notes::commands::demote_note(
+        id,
+        expected_revision,
+        &settings::checkbox_states(&settings)?,
+        &*state.active()?,
+    )
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn get_open_items(
+    filter: Option<ItemFilter>,
+    state: State<VaultsState>,
+    settings: State<SettingsState>,
+) -> AppResult<Vec<OpenItem>> {
+    notes::commands::get_open_items(
+        filter.unwrap_or_default(),
+        &settings::checkbox_states(&settings)?,
+        &*state.active()?,
+    )
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn run_query(
+    query: String,
+    state: State<VaultsState>,
+    settings: State<SettingsState>,
+) -> AppResult<Vec<notes::model::NotePreview>> {
+    notes::commands::run_query(
+        query,
+        &settings::checkbox_states(&settings)?,
+        &*state.active()?,
+    )
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn list_saved_queries(state: State<VaultsState>) -> AppResult<Vec<SavedQuery>> {
+    notes::commands::list_saved_queries(&*state.active()?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn save_query(
+    name: String,
+    query: String,
+    state: State<VaultsState>,
+) -> AppResult<Vec<SavedQuery>> {
+    notes::commands::save_query(name, query, &*state.active()?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn delete_saved_query(name: String, state: State<VaultsState>) -> AppResult<Vec<SavedQuery>> {
+    notes::commands::delete_saved_query(name, &*state.active()?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn run_saved_query(
+    name: String,
+    state: State<VaultsState>,
+    settings: State<SettingsState>,
+) -> AppResult<Vec<notes::model::NotePreview>> {
+    notes::commands::run_saved_query(
+        name,
+        &settings::checkbox_states(&settings)?,
+        &*state.active()?,
+    )
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn cycle_note_checkbox(
+    note_id: String,
+    line: usize,
+    expected_revision: Option<u64>,
+    state: State<VaultsState>,
+    settings: State<SettingsState>,
+) -> AppResult<Note> {
+    notes::commands::cycle_note_checkbox(
+        note_id,
+        line,
+        expected_revision,
+        &settings::checkbox_states(&settings)?,
+        settings::auto_complete_tasks(&settings)?,
+        &*state.active()?,
+    )
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn edit_note_checkbox(
+    note_id: String,
+    line: usize,
+    text: String,
+    expected_revision: Option<u64>,
+    state: State<VaultsState>,
+) -> AppResult<Note> {
+    notes::commands::edit_note_checkbox(note_id, line, text, expected_revision, &*state.active()?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn delete_note_checkbox(
+    note_id: String,
+    line: usize,
+    expected_revision: Option<u64>,
+    state: State<VaultsState>,
+    settings: State<SettingsState>,
+) -> AppResult<Note> {
+    notes::commands::delete_note_checkbox(
+        note_id,
+        line,
+        expected_revision,
+        &settings::checkbox_states(&settings)?,
+        settings::auto_complete_tasks(&settings)?,
+        &*state.active()?,
+    )
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn move_note_checkbox(
+    note_id: String,
+    line: usize,
+    index: usize,
+    expected_revision: Option<u64>,
+    state: State<VaultsState>,
+) -> AppResult<Note> {
+    notes::commands::move_note_checkbox(note_id, line, index, expected_revision, &*state.active()?)
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    let settings = SettingsState::new();
+    let initial_timer = settings
+        .settings
+        .lock()
+        .map(|settings| pomodoro::commands::initial_state(&settings.pomodoro))
+        .expect("settings lock poisoned during startup");
+
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
-        .manage(NotesState::new())
+        .manage(VaultsState::new())
         .manage(TimerState {
-            timer: Mutex::new(PomodoroState {
-                work_duration: to_seconds(25),
-                break_duration: to_seconds(5),
-                remaining: to_seconds(25),
-                is_break: false,
-                is_paused: false,
-            }),
+            timer: Mutex::new(initial_timer),
+        })
+        .manage(settings)
+        .setup(|app| {
+            pomodoro::commands::spawn_timer_task(app.handle().clone());
+            notes::reminders::spawn_reminder_task(app.handle().clone());
+            app.state::<VaultsState>().attach(app.handle().clone())?;
+            Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             greet,
             create_note,
             get_notes,
             get_note,
+            search_notes,
+            list_tags,
+            rename_tag,
+            get_overdue_notes,
+            get_notes_due_today,
+            get_upcoming_notes,
+            set_note_schedule,
+            set_task_status,
+            set_note_recurrence,
             update_note,
+            create_child_note,
+            move_note,
             delete_note,
             load_all_notes,
+            get_unreadable_notes,
+            get_scan_failures,
             parse_checkboxes,
+            get_checkbox_states,
+            update_checkbox_states,
+            get_auto_complete_tasks,
+            set_auto_complete_tasks,
             update_note_checkbox_status,
+            cycle_note_checkbox,
+            get_open_items,
+            run_query,
+            list_saved_queries,
+            save_query,
+            delete_saved_query,
+            run_saved_query,
+            promote_checkbox,
+            demote_note,
+            edit_note_checkbox,
+            delete_note_checkbox,
+            move_note_checkbox,
+            // Vault commands
+            vaults::commands::list_vaults,
+            vaults::commands::add_vault,
+            vaults::commands::switch_vault,
+            vaults::commands::remove_vault,
             // Pomodoro commands
             pomodoro::commands::init_timer,
             pomodoro::commands::get_timer_state,
             pomodoro::commands::start_work,
             pomodoro::commands::start_break,
+            pomodoro::commands::start_long_break,
             pomodoro::commands::tick_timer,
             pomodoro::commands::pause_timer,
             pomodoro::commands::resume_timer,
@@ -101,6 +500,13 @@ pub fn run() {
             // pomodoro::commands::check_break_finished,
             pomodoro::commands::update_work_duration,
             pomodoro::commands::update_break_duration,
+            pomodoro::commands::update_long_break_duration,
+            pomodoro::commands::update_sessions_before_long_break,
+            pomodoro::commands::get_pomodoro_settings,
+            pomodoro::commands::update_pomodoro_settings,
+            pomodoro::commands::set_active_note,
+            pomodoro::commands::get_session_history,
+            pomodoro::commands::get_sessions_for_day,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
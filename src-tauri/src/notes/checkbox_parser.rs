@@ -1,46 +1,390 @@
+use super::due::{self, DATE_FORMAT};
+use super::tags;
+use crate::error::{AppError, AppResult};
 use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+const TAB_WIDTH: usize = 4;
+
+/// How done an item is once its sub-items are taken into account.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Progress {
+    #[default]
+    Unchecked,
+    /// Some sub-items are done, but not all of them.
+    Partial,
+    Checked,
+}
+
+/// What the character between the brackets means.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckboxState {
+    #[default]
+    Open,
+    InProgress,
+    Done,
+    Cancelled,
+    Deferred,
+    Question,
+    /// A mark that isn't configured.
+    Other,
+}
+
+impl CheckboxState {
+    /// Nothing is left to do, either way.
+    pub fn is_closed(self) -> bool {
+        matches!(self, CheckboxState::Done | CheckboxState::Cancelled)
+    }
+}
+
+/// One configured state and the character that writes it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateMark {
+    pub mark: char,
+    pub state: CheckboxState,
+}
+
+/// The Obsidian-style marks, in the order an item cycles through them.
+pub fn default_states() -> Vec<StateMark> {
+    [
+        (' ', CheckboxState::Open),
+        ('/', CheckboxState::InProgress),
+        ('x', CheckboxState::Done),
+        ('-', CheckboxState::Cancelled),
+        ('>', CheckboxState::Deferred),
+        ('?', CheckboxState::Question),
+    ]
+    .into_iter()
+    .map(|(mark, state)| StateMark { mark, state })
+    .collect()
+}
+
+/// Marks can't hold what would break the `[.]` syntax.
+pub fn validate_states(states: &[StateMark]) -> AppResult<()> {
+    let mut seen = Vec::new();
+    for state in states {
+        if !state.mark.is_ascii() || state.mark.is_ascii_control() || "[]".contains(state.mark) {
+            return Err(
+                AppError::invalid_input("Checkbox mark must be a plain character")
+                    .with_context(state.mark.escape_default()),
+            );
+        }
+        if seen.contains(&state.mark) {
+            return Err(
+                AppError::invalid_input("Checkbox mark is used twice").with_context(state.mark)
+            );
+        }
+        seen.push(state.mark);
+    }
+
+    for required in [CheckboxState::Open, CheckboxState::Done] {
+        if !states.iter().any(|state| state.state == required) {
+            return Err(AppError::invalid_input(
+                "Checkbox states need an open and a done mark",
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// `X` counts as `x` unless it's configured itself.
+fn state_of(mark: char, states: &[StateMark]) -> CheckboxState {
+    let find = |mark: char| states.iter().find(|state| state.mark == mark);
+    find(mark)
+        .or_else(|| find(mark.to_ascii_lowercase()))
+        .map_or(CheckboxState::Other, |state| state.state)
+}
+
+/// The first mark configured for `state`.
+fn mark_of(state: CheckboxState, states: &[StateMark]) -> Option<char> {
+    states
+        .iter()
+        .find(|configured| configured.state == state)
+        .map(|configured| configured.mark)
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Low,
+    Medium,
+    High,
+}
+
+/// Fields written inline in an item, as in
+/// `call vendor due:2026-11-02 !high ~2🍅 @phone +launch`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ItemMeta {
+    /// The text with the metadata words taken out.
+    pub title: String,
+    pub due: Option<String>,
+    pub priority: Option<Priority>,
+    /// Estimated pomodoros.
+    pub estimate: Option<u32>,
+    pub contexts: Vec<String>,
+    pub projects: Vec<String>,
+}
+
+pub(crate) fn parse_priority(level: &str) -> Option<Priority> {
+    match level.to_lowercase().as_str() {
+        "high" | "h" | "1" => Some(Priority::High),
+        "medium" | "med" | "m" | "2" => Some(Priority::Medium),
+        "low" | "l" | "3" => Some(Priority::Low),
+        _ => None,
+    }
+}
+
+fn parse_estimate(count: &str) -> Option<u32> {
+    count
+        .trim_end_matches('🍅')
+        .parse()
+        .ok()
+        .filter(|&count| count > 0)
+}
+
+/// Sets `slot` unless it's already set, so a second `due:` stays text.
+fn fill<T>(slot: &mut Option<T>, value: Option<T>) -> bool {
+    match value {
+        Some(value) if slot.is_none() => {
+            *slot = Some(value);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Contexts and projects are named like tags.
+fn add_name(names: &mut Vec<String>, name: &str) -> bool {
+    if name.starts_with('#') {
+        return false;
+    }
+    let Some(name) = tags::normalize(name) else {
+        return false;
+    };
+    if !names.contains(&name) {
+        names.push(name);
+    }
+    true
+}
+
+impl ItemMeta {
+    /// Takes `word` if it is a metadata word. Anything malformed, like
+    /// `due:soon` or an email address, stays part of the title.
+    fn read(&mut self, word: &str) -> bool {
+        if let Some(date) = word.strip_prefix("due:") {
+            let date = due::parse_date(date).ok();
+            fill(
+                &mut self.due,
+                date.map(|day| day.format(DATE_FORMAT).to_string()),
+            )
+        } else if let Some(level) = word.strip_prefix('!') {
+            fill(&mut self.priority, parse_priority(level))
+        } else if let Some(count) = word.strip_prefix('~') {
+            fill(&mut self.estimate, parse_estimate(count))
+        } else if let Some(context) = word.strip_prefix('@') {
+            add_name(&mut self.contexts, context)
+        } else if let Some(project) = word.strip_prefix('+') {
+            add_name(&mut self.projects, project)
+        } else {
+            false
+        }
+    }
+}
+
+pub fn parse_meta(text: &str) -> ItemMeta {
+    let mut meta = ItemMeta::default();
+    let title: Vec<&str> = text
+        .split_whitespace()
+        .filter(|word| !meta.read(word))
+        .collect();
+    ItemMeta {
+        title: title.join(" "),
+        ..meta
+    }
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Checkbox {
+    /// The text as written, metadata included; edits keep it byte for byte.
     pub text: String,
+    #[serde(flatten)]
+    pub meta: ItemMeta,
+    /// The character between the brackets, exactly as written.
+    pub mark: char,
+    pub state: CheckboxState,
+    /// Whether `state` is `Done`.
     pub completed: bool,
+    /// Nesting level, 0 for top-level items.
+    #[serde(default)]
+    pub depth: usize,
+    /// Line of the content the item sits on, counting from 0.
+    #[serde(default)]
+    pub line: usize,
+    /// Follows the item's own state for a leaf and the children for a
+    /// parent. Cancelled items count as finished.
+    #[serde(default)]
+    pub progress: Progress,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Checkbox>,
 }
 
-fn parse_checkbox_line(line: &str) -> Option<Checkbox> {
-    line.trim()
-        .strip_prefix('-')
-        .or_else(|| line.trim().strip_prefix('*'))
-        .and_then(|after_dash| after_dash.trim_start().strip_prefix('['))
-        .and_then(|after_bracket| after_bracket.split_once(']'))
-        .filter(|(checkbox_char, _)| checkbox_char.len() == 1)
-        .and_then(|(checkbox_char, rest)| {
-            checkbox_char.chars().next().map(|ch| {
-                let is_completed = matches!(ch, 'x' | 'X');
-                let text = rest
-                    .trim_start()
-                    .strip_prefix('-')
-                    .map(|s| s.trim_start())
-                    .unwrap_or_else(|| rest.trim_start())
-                    .to_string();
-                (text, is_completed)
-            })
+/// Width of the leading whitespace, with tabs rounded up to the next stop.
+fn indent_width(line: &str) -> usize {
+    line.chars()
+        .take_while(|c| c.is_whitespace())
+        .fold(0, |width, c| match c {
+            '\t' => (width / TAB_WIDTH + 1) * TAB_WIDTH,
+            _ => width + 1,
         })
-        .filter(|(text, _)| !text.is_empty())
-        .map(|(text, completed)| Checkbox { text, completed })
 }
 
-pub fn parse_checkboxes(content: &str) -> Vec<Checkbox> {
-    content.lines().filter_map(parse_checkbox_line).collect()
+fn own_progress(state: CheckboxState) -> Progress {
+    if state.is_closed() {
+        Progress::Checked
+    } else {
+        Progress::Unchecked
+    }
+}
+
+/// Byte positions within a checkbox line.
+struct Span {
+    /// The character between the brackets.
+    mark: usize,
+    text: Range<usize>,
+}
+
+fn locate(line: &str) -> Option<Span> {
+    let trimmed = line.trim_end();
+    let offset = |rest: &str| trimmed.len() - rest.len();
+
+    let rest = trimmed.trim_start();
+    let rest = rest.strip_prefix('-').or_else(|| rest.strip_prefix('*'))?;
+    let rest = rest.trim_start().strip_prefix('[')?;
+    let (mark, after) = rest.split_once(']')?;
+    if mark.len() != 1 {
+        return None;
+    }
+    let after = after.trim_start();
+    let text = after
+        .strip_prefix('-')
+        .map(str::trim_start)
+        .unwrap_or(after);
+
+    (!text.is_empty()).then(|| Span {
+        mark: offset(rest),
+        text: offset(text)..trimmed.len(),
+    })
+}
+
+fn mark_at(line: &str, span: &Span) -> char {
+    line[span.mark..].chars().next().unwrap_or(' ')
+}
+
+/// Text of the checkbox on `line`, if it is one.
+pub(crate) fn checkbox_text(line: &str) -> Option<&str> {
+    locate(line).map(|span| &line[span.text])
+}
+
+pub(crate) fn parse_checkbox_line(line: &str, states: &[StateMark]) -> Option<Checkbox> {
+    locate(line).map(|span| {
+        let mark = mark_at(line, &span);
+        let state = state_of(mark, states);
+        let text = &line[span.text];
+        Checkbox {
+            text: text.to_string(),
+            meta: parse_meta(text),
+            mark,
+            state,
+            completed: state == CheckboxState::Done,
+            depth: 0,
+            line: 0,
+            progress: own_progress(state),
+            children: Vec::new(),
+        }
+    })
+}
+
+/// Sets depths and works out each parent's progress from its children.
+fn finish(checkbox: Checkbox, depth: usize) -> Checkbox {
+    let children: Vec<Checkbox> = checkbox
+        .children
+        .into_iter()
+        .map(|child| finish(child, depth + 1))
+        .collect();
+
+    let progress = if children.is_empty() {
+        own_progress(checkbox.state)
+    } else if children.iter().all(|c| c.progress == Progress::Checked) {
+        Progress::Checked
+    } else if children.iter().any(|c| c.progress != Progress::Unchecked) {
+        Progress::Partial
+    } else {
+        Progress::Unchecked
+    };
+
+    Checkbox {
+        depth,
+        progress,
+        children,
+        ..checkbox
+    }
+}
+
+/// Moves the innermost open item into its parent, or into `roots`.
+fn close_last(open: &mut Vec<(usize, Checkbox)>, roots: &mut Vec<Checkbox>) {
+    if let Some((_, closed)) = open.pop() {
+        match open.last_mut() {
+            Some((_, parent)) => parent.children.push(closed),
+            None => roots.push(closed),
+        }
+    }
+}
+
+/// Checkbox items of `content` as a tree: an item belongs to the closest
+/// item above it that is indented less. Other lines don't break the tree.
+pub fn parse_checkboxes(content: &str, states: &[StateMark]) -> Vec<Checkbox> {
+    let mut roots = Vec::new();
+    let mut open: Vec<(usize, Checkbox)> = Vec::new();
+
+    for (index, line) in content.lines().enumerate() {
+        let Some(checkbox) = parse_checkbox_line(line, states) else {
+            continue;
+        };
+        let indent = indent_width(line);
+        while open
+            .last()
+            .is_some_and(|(open_indent, _)| *open_indent >= indent)
+        {
+            close_last(&mut open, &mut roots);
+        }
+        open.push((
+            indent,
+            Checkbox {
+                line: index,
+                ..checkbox
+            },
+        ));
+    }
+    while !open.is_empty() {
+        close_last(&mut open, &mut roots);
+    }
+
+    roots.into_iter().map(|root| finish(root, 0)).collect()
+}
+
+/// Every item of the tree, parents before their children.
+pub fn flatten_checkboxes(checkboxes: &[Checkbox]) -> Vec<&Checkbox> {
+    checkboxes
+        .iter()
+        .flat_map(|checkbox| {
+            std::iter::once(checkbox).chain(flatten_checkboxes(&checkbox.children))
+        })
+        .collect()
 }
 
-// pub fn parse_checkboxes_with_positions(content: &str) -> Vec<(Checkbox, usize)> {
-//     content
-//         .lines()
-//         .enumerate()
-//         .filter_map(|(i, line)| parse_checkbox_line(line).map(|cb| (cb, i)))
-//         .collect()
-// }
-//
 // pub fn format_checkboxes(checkboxes: &[Checkbox]) -> String {
 //     checkboxes
 //         .iter()
@@ -52,50 +396,505 @@ pub fn parse_checkboxes(content: &str) -> Vec<Checkbox> {
 //         .join("\n")
 // }
 
-pub fn update_checkbox_in_content(content: &str, checkbox_text: &str, new_status: bool) -> String {
-    content
-        .lines()
-        .map(|line| {
-            parse_checkbox_line(line)
-                .filter(|checkbox| checkbox.text == checkbox_text)
-                .map(|checkbox| {
-                    let checkbox_state = if new_status { "x" } else { " " };
-                    format!("- [{}] {}", checkbox_state, checkbox.text)
-                })
-                .unwrap_or_else(|| line.to_string())
+/// Lines of `content` with their line endings, so joining them gives back
+/// every byte.
+fn split_lines(content: &str) -> Vec<&str> {
+    content.split_inclusive('\n').collect()
+}
+
+fn line_body(line: &str) -> &str {
+    line.trim_end_matches(['\n', '\r'])
+}
+
+fn checkbox_not_found(line: usize) -> AppError {
+    AppError::not_found("Checkbox not found").with_context(format!("line {}", line))
+}
+
+/// `line` with only the character between its brackets changed, so the
+/// indentation, bullet, text and line ending stay as they were.
+fn with_mark(line: &str, span: &Span, mark: char) -> String {
+    format!("{}{}{}", &line[..span.mark], mark, &line[span.mark + 1..])
+}
+
+/// Rewrites the checkbox on line `index`, leaving every other line alone.
+fn edit_line<F>(content: &str, index: usize, edit: F) -> AppResult<String>
+where
+    F: FnOnce(&str, Span) -> AppResult<String>,
+{
+    let lines = split_lines(content);
+    let line = lines.get(index).ok_or_else(|| checkbox_not_found(index))?;
+    let span = locate(line_body(line)).ok_or_else(|| checkbox_not_found(index))?;
+    let edited = edit(line, span)?;
+
+    Ok(lines[..index]
+        .iter()
+        .copied()
+        .chain(std::iter::once(edited.as_str()))
+        .chain(lines[index + 1..].iter().copied())
+        .collect())
+}
+
+/// The first configured done or open mark.
+fn completion_mark(completed: bool, states: &[StateMark]) -> char {
+    if completed {
+        mark_of(CheckboxState::Done, states).unwrap_or('x')
+    } else {
+        mark_of(CheckboxState::Open, states).unwrap_or(' ')
+    }
+}
+
+pub fn set_checkbox_status(
+    content: &str,
+    line: usize,
+    completed: bool,
+    states: &[StateMark],
+) -> AppResult<String> {
+    let mark = completion_mark(completed, states);
+    edit_line(content, line, |line, span| Ok(with_mark(line, &span, mark)))
+}
+
+/// Moves the checkbox on `line` to the next configured state, wrapping
+/// around. Unconfigured marks go to the first state.
+pub fn cycle_checkbox_state(content: &str, line: usize, states: &[StateMark]) -> AppResult<String> {
+    edit_line(content, line, |line, span| {
+        let current = mark_at(line, &span);
+        let next = states
+            .iter()
+            .position(|state| state.mark == current)
+            .map_or(0, |i| (i + 1) % states.len());
+        let mark = states.get(next).map_or(' ', |state| state.mark);
+        Ok(with_mark(line, &span, mark))
+    })
+}
+
+pub fn set_checkbox_text(content: &str, line: usize, text: &str) -> AppResult<String> {
+    let text = text.trim();
+    if text.is_empty() || text.contains(['\n', '\r']) {
+        return Err(AppError::invalid_input(
+            "Checkbox text must be a single non-empty line",
+        ));
+    }
+    edit_line(content, line, |line, span| {
+        Ok(format!(
+            "{}{}{}",
+            &line[..span.text.start],
+            text,
+            &line[span.text.end..]
+        ))
+    })
+}
+
+/// Removes the checkbox on line `line`. Its sub-items stay and move up to
+/// whichever item is now above them.
+pub fn delete_checkbox(content: &str, line: usize) -> AppResult<String> {
+    let mut lines = split_lines(content);
+    lines
+        .get(line)
+        .filter(|text| locate(line_body(text)).is_some())
+        .ok_or_else(|| checkbox_not_found(line))?;
+    lines.remove(line);
+
+    // The last line had no line ending, so the one now last shouldn't either
+    let mut rest: String = lines.concat();
+    if line == lines.len() && !content.ends_with('\n') {
+        let body_len = line_body(&rest).len();
+        rest.truncate(body_len);
+    }
+    Ok(rest)
+}
+
+/// The item on `line` and the list it belongs to.
+fn find_with_siblings(checkboxes: &[Checkbox], line: usize) -> Option<(&[Checkbox], usize)> {
+    checkboxes
+        .iter()
+        .position(|checkbox| checkbox.line == line)
+        .map(|position| (checkboxes, position))
+        .or_else(|| {
+            checkboxes
+                .iter()
+                .find_map(|checkbox| find_with_siblings(&checkbox.children, line))
         })
-        .collect::<Vec<_>>()
-        .join("\n")
-}
-
-// pub fn update_checkbox(
-//     checkboxes: Vec<Checkbox>,
-//     index: usize,
-//     completed: bool,
-// ) -> Option<Vec<Checkbox>> {
-//     (index < checkboxes.len()).then(|| {
-//         checkboxes
-//             .into_iter()
-//             .enumerate()
-//             .map(|(i, mut cb)| {
-//                 if i == index {
-//                     cb.completed = completed;
-//                 }
-//                 cb
-//             })
-//             .collect()
-//     })
-// }
+}
 
-// Count completed checkboxes
-// pub fn count_completed(checkboxes: &[Checkbox]) -> usize {
-//     checkboxes.iter().filter(|cb| cb.completed).count()
-// }
-//
-// /// Calculate progress percentage
-// pub fn get_progress(checkboxes: &[Checkbox]) -> f32 {
-//     if checkboxes.is_empty() {
-//         return 0.0;
-//     }
-//     (count_completed(checkboxes) as f32 / checkboxes.len() as f32) * 100.0
-// }
+/// Last line taken up by an item and its sub-items.
+fn last_line(checkbox: &Checkbox) -> usize {
+    checkbox.children.last().map_or(checkbox.line, last_line)
+}
+
+/// Moves the checkbox on `line`, sub-items included, to position `index`
+/// among its siblings. Lines are moved as they are, nothing is re-indented.
+pub fn move_checkbox(content: &str, line: usize, index: usize) -> AppResult<String> {
+    // Only the layout matters here, not what the marks mean
+    let tree = parse_checkboxes(content, &[]);
+    let (siblings, position) =
+        find_with_siblings(&tree, line).ok_or_else(|| checkbox_not_found(line))?;
+    let moved = &siblings[position];
+    let others: Vec<&Checkbox> = siblings
+        .iter()
+        .filter(|sibling| sibling.line != line)
+        .collect();
+
+    let block = moved.line..last_line(moved) + 1;
+    let target = match others.get(index) {
+        Some(next) => next.line,
+        None => match others.last() {
+            Some(last) => last_line(last) + 1,
+            None => return Ok(content.to_string()),
+        },
+    };
+
+    let mut lines = ended_lines(content);
+    let block_lines: Vec<String> = lines.drain(block.clone()).collect();
+    let at = if target > block.start {
+        target - block.len()
+    } else {
+        target
+    };
+    lines.splice(at..at, block_lines);
+    Ok(join_like(lines, content))
+}
+
+fn line_ending(content: &str) -> &'static str {
+    if content.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    }
+}
+
+/// Lines of `content`, each with a line ending, the last one included, so
+/// they can be shuffled around.
+fn ended_lines(content: &str) -> Vec<String> {
+    let mut lines: Vec<String> = split_lines(content)
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    if let Some(last) = lines.last_mut().filter(|last| !last.ends_with('\n')) {
+        last.push_str(line_ending(content));
+    }
+    lines
+}
+
+/// Joins lines from `ended_lines`, taking off the final line ending again if
+/// `content` didn't have one.
+fn join_like(lines: Vec<String>, content: &str) -> String {
+    let mut joined = lines.concat();
+    if !content.ends_with('\n') {
+        let body_len = line_body(&joined).len();
+        joined.truncate(body_len);
+    }
+    joined
+}
+
+/// `lines` without the indentation of the first non-blank one.
+fn dedent(lines: &[String]) -> String {
+    let indent = lines
+        .iter()
+        .find(|line| !line.trim().is_empty())
+        .map_or("", |line| &line[..line.len() - line.trim_start().len()]);
+    let dedented: String = lines
+        .iter()
+        .map(|line| line.strip_prefix(indent).unwrap_or(line.trim_start()))
+        .collect();
+    line_body(&dedented).to_string()
+}
+
+/// A checkbox cut out of its content by `take_checkbox`.
+pub struct TakenCheckbox {
+    pub checkbox: Checkbox,
+    /// The lines under the item, sub-items included, dedented.
+    pub body: String,
+    /// The content without the item.
+    pub rest: String,
+}
+
+/// Removes the checkbox on `line` together with its sub-items.
+pub fn take_checkbox(content: &str, line: usize, states: &[StateMark]) -> AppResult<TakenCheckbox> {
+    let tree = parse_checkboxes(content, states);
+    let (siblings, index) =
+        find_with_siblings(&tree, line).ok_or_else(|| checkbox_not_found(line))?;
+    let checkbox = siblings[index].clone();
+
+    let mut lines = ended_lines(content);
+    let block: Vec<String> = lines
+        .drain(checkbox.line..last_line(&checkbox) + 1)
+        .collect();
+    Ok(TakenCheckbox {
+        body: dedent(&block[1..]),
+        rest: join_like(lines, content),
+        checkbox,
+    })
+}
+
+/// Adds a top-level checkbox with `body` indented under it, before the
+/// top-level item at `position` or after the last one. Content without
+/// checkboxes gets it at the end. Blank lines inside `body` stay, so its
+/// paragraphs don't run together.
+pub fn insert_checkbox(
+    content: &str,
+    position: usize,
+    text: &str,
+    completed: bool,
+    body: &str,
+    states: &[StateMark],
+) -> String {
+    let tree = parse_checkboxes(content, &[]);
+    let mut lines = ended_lines(content);
+    let at = match tree.get(position) {
+        Some(next) => next.line,
+        None => tree.last().map_or(lines.len(), |last| last_line(last) + 1),
+    };
+
+    let ending = line_ending(content);
+    let mark = completion_mark(completed, states);
+    let block = std::iter::once(format!("- [{}] {}{}", mark, text, ending)).chain(
+        body.trim_end()
+            .lines()
+            .skip_while(|line| line.trim().is_empty())
+            .map(|line| match line.trim().is_empty() {
+                true => ending.to_string(),
+                false => format!("  {}{}", line, ending),
+            }),
+    );
+    lines.splice(at..at, block);
+    join_like(lines, content)
+}
+
+/// Every checkbox back to `[ ]`; nothing else changes.
+pub fn reset_checkboxes(content: &str) -> String {
+    split_lines(content)
+        .into_iter()
+        .map(|line| match locate(line_body(line)) {
+            Some(span) => with_mark(line, &span, ' '),
+            None => line.to_string(),
+        })
+        .collect()
+}
+
+/// How many items there are and how many of them are done. Only items
+/// without sub-items count, since a parent's progress follows theirs;
+/// cancelled items count as neither.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub total: u32,
+    pub completed: u32,
+}
+
+impl Tally {
+    pub fn of(checkboxes: &[Checkbox]) -> Tally {
+        flatten_checkboxes(checkboxes)
+            .into_iter()
+            .filter(|checkbox| {
+                checkbox.children.is_empty() && checkbox.state != CheckboxState::Cancelled
+            })
+            .fold(Tally::default(), |tally, checkbox| Tally {
+                total: tally.total + 1,
+                completed: tally.completed + u32::from(checkbox.completed),
+            })
+    }
+
+    pub fn add(self, other: Tally) -> Tally {
+        Tally {
+            total: self.total + other.total,
+            completed: self.completed + other.completed,
+        }
+    }
+
+    /// Rounded down, so 100 means every item is done. `None` without items.
+    pub fn percent(self) -> Option<u8> {
+        (self.total > 0).then(|| (self.completed * 100 / self.total) as u8)
+    }
+
+    pub fn is_complete(self) -> bool {
+        self.total > 0 && self.completed == self.total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Vec<Checkbox> {
+        parse_checkboxes(content, &default_states())
+    }
+
+    fn outline(checkboxes: &[Checkbox]) -> Vec<(usize, usize, &str, Progress)> {
+        flatten_checkboxes(checkboxes)
+            .into_iter()
+            .map(|c| (c.depth, c.line, c.text.as_str(), c.progress))
+            .collect()
+    }
+
+    #[test]
+    fn test_nested_checkboxes() {
+        let content = "- [ ] Trip\n  - [x] Book flight\n  - [ ] Pack\n\t- [x] Socks\n\
+                       \t- [x] Charger\nnotes in between\n    - [ ] Passport\n- [x] Done";
+        let parsed = parse(content);
+
+        assert_eq!(
+            outline(&parsed),
+            [
+                (0, 0, "Trip", Progress::Partial),
+                (1, 1, "Book flight", Progress::Checked),
+                (1, 2, "Pack", Progress::Partial),
+                (2, 3, "Socks", Progress::Checked),
+                (2, 4, "Charger", Progress::Checked),
+                (2, 6, "Passport", Progress::Unchecked),
+                (0, 7, "Done", Progress::Checked),
+            ]
+        );
+        assert!(!parsed[0].children[1].completed);
+    }
+
+    #[test]
+    fn test_tally_counts_sub_items() {
+        let content = "- [ ] Trip\n  - [x] Book\n  - [-] Hotel\n  - [/] Pack\n- [x] Visa";
+        let tally = Tally::of(&parse(content));
+        assert_eq!(
+            tally,
+            Tally {
+                total: 3,
+                completed: 2
+            }
+        );
+        assert_eq!(tally.percent(), Some(66));
+        assert!(!tally.is_complete());
+        // An unticked parent is done once all of its sub-items are
+        assert!(Tally::of(&parse("- [ ] Trip\n  - [x] a\n  - [x] b")).is_complete());
+        assert_eq!(Tally::of(&parse("no items")).percent(), None);
+        assert!(Tally::of(&parse("- [x] a\n- [-] b")).is_complete());
+    }
+
+    #[test]
+    fn test_edits_target_one_line() {
+        let content = "## Mon\r\n- [ ] Review\r\n## Tue\r\n  * [ ]  Review \r\n";
+
+        let toggled = set_checkbox_status(content, 3, true, &default_states()).unwrap();
+        assert_eq!(
+            toggled,
+            "## Mon\r\n- [ ] Review\r\n## Tue\r\n  * [x]  Review \r\n"
+        );
+
+        let edited = set_checkbox_text(content, 1, " Plan ").unwrap();
+        assert_eq!(
+            edited,
+            "## Mon\r\n- [ ] Plan\r\n## Tue\r\n  * [ ]  Review \r\n"
+        );
+
+        assert_eq!(
+            delete_checkbox(content, 1).unwrap(),
+            "## Mon\r\n## Tue\r\n  * [ ]  Review \r\n"
+        );
+        assert_eq!(delete_checkbox("a\n- [ ] b", 1).unwrap(), "a");
+
+        assert!(set_checkbox_status(content, 0, true, &default_states()).is_err());
+        assert!(set_checkbox_text(content, 1, "two\nlines").is_err());
+    }
+
+    #[test]
+    fn test_move_checkbox_with_sub_items() {
+        let content = "- [ ] A\n  - [x] A1\n- [ ] B\n- [ ] C";
+
+        assert_eq!(
+            move_checkbox(content, 0, 2).unwrap(),
+            "- [ ] B\n- [ ] C\n- [ ] A\n  - [x] A1"
+        );
+        assert_eq!(
+            move_checkbox(content, 3, 0).unwrap(),
+            "- [ ] C\n- [ ] A\n  - [x] A1\n- [ ] B"
+        );
+        assert_eq!(move_checkbox(content, 1, 5).unwrap(), content);
+    }
+
+    #[test]
+    fn test_states_round_trip_and_cycle() {
+        let content = "- [/] Draft\n- [-] Drop\n- [X] Ship\n- [!] Urgent";
+        let states: Vec<(char, CheckboxState)> = parse(content)
+            .iter()
+            .map(|checkbox| (checkbox.mark, checkbox.state))
+            .collect();
+        assert_eq!(
+            states,
+            [
+                ('/', CheckboxState::InProgress),
+                ('-', CheckboxState::Cancelled),
+                ('X', CheckboxState::Done),
+                ('!', CheckboxState::Other),
+            ]
+        );
+
+        let cycled = cycle_checkbox_state(content, 0, &default_states()).unwrap();
+        assert_eq!(cycled, "- [x] Draft\n- [-] Drop\n- [X] Ship\n- [!] Urgent");
+        let cycled = cycle_checkbox_state(content, 3, &default_states()).unwrap();
+        assert!(cycled.ends_with("- [ ] Urgent"));
+        let last = cycle_checkbox_state("- [?] Why", 0, &default_states()).unwrap();
+        assert_eq!(last, "- [ ] Why");
+    }
+
+    #[test]
+    fn test_reset_keeps_indentation() {
+        let content = "- [x] Trip\n    * [/] Pack - bag\n\t-  [X] Socks\n";
+        assert_eq!(
+            reset_checkboxes(content),
+            "- [ ] Trip\n    * [ ] Pack - bag\n\t-  [ ] Socks\n"
+        );
+    }
+
+    #[test]
+    fn test_item_metadata() {
+        let checkbox = &parse("- [ ] call vendor due:2026-11-2 !high ~2🍅 @Phone +launch")[0];
+        assert_eq!(
+            checkbox.meta,
+            ItemMeta {
+                title: "call vendor".to_string(),
+                due: Some("2026-11-02".to_string()),
+                priority: Some(Priority::High),
+                estimate: Some(2),
+                contexts: vec!["phone".to_string()],
+                projects: vec!["launch".to_string()],
+            }
+        );
+        assert_eq!(
+            checkbox.text,
+            "call vendor due:2026-11-2 !high ~2🍅 @Phone +launch"
+        );
+
+        let plain = parse_meta("mail a@b.c due:soon !! ~0 + @ 1+1");
+        assert_eq!(plain.title, "mail a@b.c due:soon !! ~0 + @ 1+1");
+        assert_eq!(
+            plain,
+            ItemMeta {
+                title: plain.title.clone(),
+                ..ItemMeta::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_take_and_insert_round_trip() {
+        let content = "Intro\r\n- [ ] A\r\n- [x] B\r\n  - [ ] B1\r\n    - [/] B2\r\n- [ ] C";
+        let taken = take_checkbox(content, 2, &default_states()).unwrap();
+        assert_eq!(taken.checkbox.text, "B");
+        assert!(taken.checkbox.completed);
+        assert_eq!(taken.body, "- [ ] B1\r\n  - [/] B2");
+        assert_eq!(taken.rest, "Intro\r\n- [ ] A\r\n- [ ] C");
+
+        let restored = insert_checkbox(&taken.rest, 1, "B", true, &taken.body, &default_states());
+        assert_eq!(restored, content);
+        assert_eq!(
+            insert_checkbox("Plan", 0, "New", false, "", &default_states()),
+            "Plan\n- [ ] New"
+        );
+        assert_eq!(
+            insert_checkbox(
+                "",
+                0,
+                "Visa",
+                false,
+                "Check rules\n\nPassport\n",
+                &default_states()
+            ),
+            "- [ ] Visa\n  Check rules\n\n  Passport"
+        );
+    }
+}
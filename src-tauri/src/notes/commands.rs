@@ -1,59 +1,139 @@
-use super::model::{note_to_preview, Note, NotePreview};
-use super::{checkbox_parser, storage, NotesState};
+use super::checkbox_parser::{self, StateMark};
+use super::due::{self, DueWindow};
+use super::listing::{self, Criteria, NoteQuery, SortKey};
+use super::model::{
+    note_tally, note_to_preview, unique_id, with_id, Note, NotePage, NotePreview, TaskStatus,
+};
+use super::open_items::{self, ItemFilter, OpenItem};
+use super::query::{Query, SavedQuery};
+use super::recurrence;
+use super::search::{self, SearchHit, SearchIndex};
+use super::tags::{self, TagCount};
+use super::{storage, NotesState};
+use crate::error::{AppError, AppResult};
+use crate::settings::{self, model::Settings, SettingsState};
 use std::sync::MutexGuard;
-use tauri::State;
 
-fn with_notes_state<T, F>(state: &State<NotesState>, transformer: F) -> Result<T, String>
+const DEFAULT_SEARCH_LIMIT: usize = 50;
+
+fn with_notes_state<T, F>(state: &NotesState, transformer: F) -> AppResult<T>
 where
-    F: FnOnce(MutexGuard<Vec<Note>>) -> Result<T, String>,
+    F: FnOnce(MutexGuard<Vec<Note>>) -> AppResult<T>,
 {
     state
         .notes
         .lock()
-        .map_err(|e| e.to_string())
+        .map_err(AppError::from)
         .and_then(transformer)
 }
 
-fn modify_notes_state<T, F>(state: &State<NotesState>, modifier: F) -> Result<T, String>
+fn modify_notes_state<T, F>(state: &NotesState, modifier: F) -> AppResult<T>
 where
-    F: FnOnce(&mut Vec<Note>) -> Result<T, String>,
+    F: FnOnce(&mut Vec<Note>) -> AppResult<T>,
 {
     state
         .notes
         .lock()
-        .map_err(|e| e.to_string())
+        .map_err(AppError::from)
         .and_then(|mut notes| modifier(&mut notes))
 }
 
-fn find_note<'a>(notes: &'a [Note], id: &str) -> Option<&'a Note> {
-    notes.iter().find(|note| note.id == id)
+fn note_not_found(id: &str) -> AppError {
+    AppError::not_found("Note not found").with_context(id)
 }
 
-fn remove_note_from_list(notes: Vec<Note>, id: &str) -> Vec<Note> {
-    notes.into_iter().filter(|note| note.id != id).collect()
+fn find_in_tree<'a>(notes: &'a [Note], id: &str) -> Option<&'a Note> {
+    notes.iter().find_map(|note| {
+        if note.id == id {
+            Some(note)
+        } else {
+            find_in_tree(&note.children, id)
+        }
+    })
+}
+
+/// Every note of the tree, children kept, parents before their children.
+fn all_notes(notes: &[Note]) -> Vec<&Note> {
+    notes
+        .iter()
+        .flat_map(|note| std::iter::once(note).chain(all_notes(&note.children)))
+        .collect()
+}
+
+fn subtree_ids(note: &Note) -> Vec<String> {
+    std::iter::once(note.id.clone())
+        .chain(note.children.iter().flat_map(subtree_ids))
+        .collect()
+}
+
+fn remove_from_tree(notes: Vec<Note>, id: &str) -> Vec<Note> {
+    notes
+        .into_iter()
+        .filter(|note| note.id != id)
+        .map(|note| Note {
+            children: remove_from_tree(note.children, id),
+            ..note
+        })
+        .collect()
 }
 
 fn add_note_to_list(notes: Vec<Note>, note: Note) -> Vec<Note> {
     [notes, vec![note]].concat()
 }
 
-fn update_timestamp(note: Note) -> Note {
-    let now = std::time::SystemTime::now()
+fn insert_note_at(notes: Vec<Note>, index: Option<usize>, note: Note) -> Vec<Note> {
+    let at = index.unwrap_or(notes.len()).min(notes.len());
+    let (before, after) = notes.split_at(at);
+    [before.to_vec(), vec![note], after.to_vec()].concat()
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap()
-        .as_secs();
+        .as_secs()
+}
 
+/// Marks a note as changed: new timestamp, next revision.
+fn touch(note: Note) -> Note {
     Note {
-        updated_at: now,
+        updated_at: unix_now(),
+        revision: note.revision + 1,
         ..note
     }
 }
 
-fn find_and_update_note<F>(
+fn update_in_tree<F>(note: Note, id: &str, updater: &F) -> (Note, Option<Note>)
+where
+    F: Fn(Note) -> Note,
+{
+    if note.id == id {
+        let updated = updater(note);
+        return (updated.clone(), Some(updated));
+    }
+
+    let (children, found) =
+        note.children
+            .into_iter()
+            .fold((Vec::new(), None), |(mut acc, found), child| {
+                if found.is_some() {
+                    acc.push(child);
+                    (acc, found)
+                } else {
+                    let (child, found) = update_in_tree(child, id, updater);
+                    acc.push(child);
+                    (acc, found)
+                }
+            });
+
+    (Note { children, ..note }, found)
+}
+
+fn find_and_update_in_tree<F>(
     notes: Vec<Note>,
     id: &str,
     updater: F,
-) -> Result<(Vec<Note>, Note), String>
+) -> AppResult<(Vec<Note>, Note)>
 where
     F: Fn(Note) -> Note,
 {
@@ -61,46 +141,417 @@ where
         notes
             .into_iter()
             .fold((Vec::new(), None), |(mut acc, found), note| {
-                if note.id == id && found.is_none() {
-                    let updated = updater(note);
-                    acc.push(updated.clone());
-                    (acc, Some(updated))
-                } else {
+                if found.is_some() {
                     acc.push(note);
-                    (acc, found)
+                    return (acc, found);
                 }
+
+                let (note, updated) = update_in_tree(note, id, &updater);
+                acc.push(note);
+                (acc, updated)
             });
 
     updated_note
-        .ok_or("Note not found".to_string())
+        .ok_or_else(|| note_not_found(id))
         .map(|updated| (new_notes, updated))
 }
 
-pub(crate) fn create_note(
-    title: String,
-    content: String,
-    state: State<NotesState>,
-) -> Result<Note, String> {
-    let note = Note::new(title, content);
-    storage::save_note(&note)?;
+fn check_revision(notes: &[Note], id: &str, expected_revision: Option<u64>) -> AppResult<()> {
+    let current = find_in_tree(notes, id).ok_or_else(|| note_not_found(id))?;
+    match expected_revision.filter(|&r| r != current.revision) {
+        Some(expected) => Err(AppError::conflict(format!(
+            "Note was changed elsewhere (revision {}, expected {})",
+            current.revision, expected
+        ))
+        .with_context(id)),
+        None => Ok(()),
+    }
+}
 
-    modify_notes_state(&state, |notes| {
+/// Reads, changes and saves one note under a single lock, so concurrent
+/// updates can't lose each other. A client passing the revision it last saw
+/// gets a conflict instead of overwriting a newer version.
+fn update_note_with<F>(
+    state: &NotesState,
+    id: &str,
+    expected_revision: Option<u64>,
+    updater: F,
+) -> AppResult<Note>
+where
+    F: FnOnce(Note) -> Note,
+{
+    try_update_note_with(state, id, expected_revision, |note| Ok(updater(note)))
+}
+
+/// Like `update_note_with`, for changes that can be refused; nothing is
+/// saved when `updater` fails.
+fn try_update_note_with<F>(
+    state: &NotesState,
+    id: &str,
+    expected_revision: Option<u64>,
+    updater: F,
+) -> AppResult<Note>
+where
+    F: FnOnce(Note) -> AppResult<Note>,
+{
+    modify_notes_state(state, |notes| {
+        update_note_in(notes, state, id, expected_revision, updater)
+    })
+}
+
+/// The work of `try_update_note_with`, for callers already holding the lock.
+fn update_note_in<F>(
+    notes: &mut Vec<Note>,
+    state: &NotesState,
+    id: &str,
+    expected_revision: Option<u64>,
+    updater: F,
+) -> AppResult<Note>
+where
+    F: FnOnce(Note) -> AppResult<Note>,
+{
+    check_revision(notes, id, expected_revision)?;
+
+    let current = find_in_tree(notes, id).ok_or_else(|| note_not_found(id))?;
+    let changed = touch(updater(current.clone())?);
+    let (new_notes, updated) = find_and_update_in_tree(notes.clone(), id, |_| changed.clone())?;
+    storage::save_single_note(&state.dir, &updated)?;
+    state.index.lock()?.upsert(&updated);
+    *notes = new_notes;
+    Ok(updated)
+}
+
+/// Like `try_update_note_with`, then, given the checkbox states to tally
+/// with, lets `complete_from_checkboxes` follow the edit under the same
+/// lock. Returns the note as it ends up.
+fn update_note_and_complete<F>(
+    state: &NotesState,
+    id: &str,
+    expected_revision: Option<u64>,
+    auto_complete: Option<&[StateMark]>,
+    updater: F,
+) -> AppResult<Note>
+where
+    F: FnOnce(Note) -> AppResult<Note>,
+{
+    modify_notes_state(state, |notes| {
+        let updated = update_note_in(notes, state, id, expected_revision, updater)?;
+        let Some(states) = auto_complete else {
+            return Ok(updated);
+        };
+        complete_from_checkboxes(notes, id, states, state)?;
+        find_in_tree(notes, id)
+            .cloned()
+            .ok_or_else(|| note_not_found(id))
+    })
+}
+
+pub(crate) fn create_note(title: String, content: String, state: &NotesState) -> AppResult<Note> {
+    modify_notes_state(state, |notes| {
+        let draft = Note::new(title, content);
+        let id = unique_id(draft.id.clone(), |candidate| {
+            find_in_tree(notes, candidate).is_some()
+        });
+        let note = with_id(draft, id);
+
+        storage::save_note(&state.dir, &note)?;
+        state.index.lock()?.upsert(&note);
         *notes = add_note_to_list(std::mem::take(notes), note.clone());
-        Ok(())
+        Ok(note)
+    })
+}
+
+/// A page of note previews. By default that's every top-level note, oldest
+/// first.
+pub(crate) fn get_notes(
+    query: NoteQuery,
+    states: &[StateMark],
+    state: &NotesState,
+) -> AppResult<NotePage> {
+    let filter = &query.filter;
+    let sort = query.sort_key();
+    let day = |date: &Option<String>| date.as_deref().map(due::parse_date).transpose();
+    let criteria = Criteria {
+        filter,
+        tag: filter.tag.as_deref().map(parse_tag).transpose()?,
+        due_from: day(&filter.due_from)?,
+        due_to: day(&filter.due_to)?,
+    };
+
+    with_notes_state(state, |notes_guard| {
+        let candidates: Vec<&Note> = match filter.parent_id.as_deref() {
+            Some(parent_id) => find_in_tree(&notes_guard, parent_id)
+                .ok_or_else(|| note_not_found(parent_id))?
+                .children
+                .iter()
+                .collect(),
+            None if filter.include_children => all_notes(&notes_guard),
+            None => notes_guard.iter().collect(),
+        };
+
+        // Progress means parsing every note, so only work it out to sort by it
+        let mut matched: Vec<(&Note, Option<u8>)> = candidates
+            .into_iter()
+            .filter(|note| criteria.matches(note))
+            .map(|note| {
+                let progress = (sort == SortKey::Progress)
+                    .then(|| note_tally(note, states).percent())
+                    .flatten();
+                (note, progress)
+            })
+            .collect();
+        listing::sort_notes(&mut matched, sort, query.direction);
+
+        Ok(NotePage {
+            total: matched.len(),
+            notes: matched
+                .into_iter()
+                .skip(query.offset)
+                .take(query.limit.unwrap_or(usize::MAX))
+                .map(|(note, _)| note_to_preview(note, states))
+                .collect(),
+        })
+    })
+}
+
+fn parse_tag(tag: &str) -> AppResult<String> {
+    tags::normalize(tag).ok_or_else(|| AppError::invalid_input("Invalid tag").with_context(tag))
+}
+
+/// Unfinished tasks due within `window`, soonest first.
+pub(crate) fn get_due_notes(
+    window: DueWindow,
+    states: &[StateMark],
+    state: &NotesState,
+) -> AppResult<Vec<NotePreview>> {
+    let today = due::today();
+    with_notes_state(state, |notes_guard| {
+        let mut due_notes: Vec<&Note> = all_notes(&notes_guard)
+            .into_iter()
+            .filter(|note| due::is_due_in(note, window, today))
+            .collect();
+        due_notes.sort_by(|a, b| {
+            due::due_date(a)
+                .cmp(&due::due_date(b))
+                .then_with(|| a.title.cmp(&b.title))
+        });
+
+        Ok(due_notes
+            .into_iter()
+            .map(|note| note_to_preview(note, states))
+            .collect())
+    })
+}
+
+/// Sets the due and scheduled days (`YYYY-MM-DD`, `None` to clear) and the
+/// reminder times of a note.
+pub(crate) fn set_note_schedule(
+    id: String,
+    due: Option<String>,
+    scheduled: Option<String>,
+    reminders: Vec<u64>,
+    expected_revision: Option<u64>,
+    state: &NotesState,
+) -> AppResult<Note> {
+    let due = due::validate_date(due)?;
+    let scheduled = due::validate_date(scheduled)?;
+    let mut reminders = reminders;
+    reminders.sort_unstable();
+    reminders.dedup();
+
+    update_note_with(state, &id, expected_revision, |note| Note {
+        due: due.clone(),
+        scheduled: scheduled.clone(),
+        reminders: reminders.clone(),
+        ..note
+    })
+}
+
+/// Sets how a task comes back once done; `None` makes it a one-off again.
+pub(crate) fn set_note_recurrence(
+    id: String,
+    recurrence: Option<String>,
+    reset_checkboxes: bool,
+    expected_revision: Option<u64>,
+    state: &NotesState,
+) -> AppResult<Note> {
+    let recurrence = recurrence::validate_rule(recurrence)?;
+    update_note_with(state, &id, expected_revision, |note| Note {
+        recurrence: recurrence.clone(),
+        reset_checkboxes,
+        ..note
+    })
+}
+
+/// Marks a note as a task and sets whether it's done. Completing a recurring
+/// task adds its next occurrence right after it, which takes the rule over
+/// so undoing and redoing the old one doesn't generate a second copy.
+pub(crate) fn set_task_status(
+    id: String,
+    is_task: bool,
+    is_done: bool,
+    expected_revision: Option<u64>,
+    state: &NotesState,
+) -> AppResult<TaskStatus> {
+    modify_notes_state(state, |notes| {
+        check_revision(notes, &id, expected_revision)?;
+        set_task_status_in(notes, state, &id, is_task, is_done)
+    })
+}
+
+/// The work of `set_task_status`, for callers already holding the lock.
+fn set_task_status_in(
+    notes: &mut Vec<Note>,
+    state: &NotesState,
+    id: &str,
+    is_task: bool,
+    is_done: bool,
+) -> AppResult<TaskStatus> {
+    let current = find_in_tree(notes, id).ok_or_else(|| note_not_found(id))?;
+
+    let completed = is_task && is_done && !current.is_done;
+    let next = completed
+        .then(|| recurrence::next_occurrence(current, due::today()))
+        .flatten()
+        .map(|draft| {
+            let id = unique_id(draft.id.clone(), |candidate| {
+                find_in_tree(notes, candidate).is_some()
+            });
+            with_id(draft, id)
+        });
+
+    let (new_notes, updated) = find_and_update_in_tree(notes.clone(), id, |note| {
+        touch(Note {
+            is_task,
+            is_done: is_task && is_done,
+            recurrence: if next.is_some() {
+                None
+            } else {
+                note.recurrence.clone()
+            },
+            ..note
+        })
     })?;
 
-    Ok(note)
+    let Some(next) = next else {
+        storage::save_single_note(&state.dir, &updated)?;
+        state.index.lock()?.upsert(&updated);
+        *notes = new_notes;
+        return Ok(TaskStatus {
+            note: updated,
+            next: None,
+        });
+    };
+
+    let new_notes = match updated.parent_id.as_deref() {
+        Some(parent_id) => {
+            let (new_notes, parent) = find_and_update_in_tree(new_notes, parent_id, |parent| {
+                let at = parent.children.iter().position(|child| child.id == id);
+                Note {
+                    children: insert_note_at(parent.children, at.map(|i| i + 1), next.clone()),
+                    ..parent
+                }
+            })?;
+            storage::save_single_note(&state.dir, &parent)?;
+            new_notes
+        }
+        None => {
+            let at = new_notes.iter().position(|note| note.id == id);
+            insert_note_at(new_notes, at.map(|i| i + 1), next.clone())
+        }
+    };
+
+    storage::save_single_note(&state.dir, &next)?;
+    storage::save_single_note(&state.dir, &updated)?;
+    let mut index = state.index.lock()?;
+    index.upsert(&next);
+    index.upsert(&updated);
+
+    *notes = new_notes;
+    Ok(TaskStatus {
+        note: updated,
+        next: Some(next),
+    })
+}
+
+pub(crate) fn list_tags(state: &NotesState) -> AppResult<Vec<TagCount>> {
+    with_notes_state(state, |notes_guard| Ok(tags::count_tags(&notes_guard)))
 }
 
-pub(crate) fn get_notes(state: State<NotesState>) -> Result<Vec<NotePreview>, String> {
-    with_notes_state(&state, |notes_guard| {
-        Ok(notes_guard.iter().map(|note| note_to_preview(note, 0)).collect())
+fn rename_tag_in_tree(
+    notes: Vec<Note>,
+    from: &str,
+    to: &str,
+    changed: &mut Vec<Note>,
+) -> Vec<Note> {
+    notes
+        .into_iter()
+        .map(|note| {
+            let note = match tags::rename_in_note(&note, from, to) {
+                Some(renamed) => {
+                    let renamed = touch(renamed);
+                    changed.push(renamed.clone());
+                    renamed
+                }
+                None => note,
+            };
+            Note {
+                children: rename_tag_in_tree(note.children, from, to, changed),
+                ..note
+            }
+        })
+        .collect()
+}
+
+/// Renames a tag in every note carrying it, nested tags (`from/...`)
+/// included. Renaming onto an existing tag merges the two.
+pub(crate) fn rename_tag(from: String, to: String, state: &NotesState) -> AppResult<Vec<TagCount>> {
+    let (from, to) = (parse_tag(&from)?, parse_tag(&to)?);
+
+    modify_notes_state(state, |notes| {
+        let mut changed = Vec::new();
+        let new_notes = rename_tag_in_tree(notes.clone(), &from, &to, &mut changed);
+        if changed.is_empty() {
+            return Err(AppError::not_found("Tag not found").with_context(&from));
+        }
+
+        changed
+            .iter()
+            .try_for_each(|note| storage::save_single_note(&state.dir, note))?;
+        let mut index = state.index.lock()?;
+        changed.iter().for_each(|note| index.upsert(note));
+
+        *notes = new_notes;
+        Ok(tags::count_tags(notes))
+    })
+}
+
+/// Ranked matches for `query` in titles, body text and checkbox items.
+pub(crate) fn search_notes(
+    query: String,
+    limit: Option<usize>,
+    states: &[StateMark],
+    state: &NotesState,
+) -> AppResult<Vec<SearchHit>> {
+    with_notes_state(state, |notes_guard| {
+        let ranked = state.index.lock()?.search(&query);
+        Ok(ranked
+            .into_iter()
+            .take(limit.unwrap_or(DEFAULT_SEARCH_LIMIT))
+            .filter_map(|(id, score)| {
+                find_in_tree(&notes_guard, &id).map(|note| SearchHit {
+                    note: note_to_preview(note, states),
+                    score,
+                    snippets: search::snippets(note, &query),
+                })
+            })
+            .collect())
     })
 }
 
-pub(crate) fn get_note(id: String, state: State<NotesState>) -> Result<Note, String> {
-    with_notes_state(&state, |notes_guard| {
-        find_note(&*notes_guard, &id)
+pub(crate) fn get_note(id: String, state: &NotesState) -> AppResult<Note> {
+    with_notes_state(state, |notes_guard| {
+        find_in_tree(&notes_guard, &id)
             .cloned()
             .map(|mut note| {
                 note.content_without_checkboxes = Some(
@@ -116,25 +567,30 @@ pub(crate) fn get_note(id: String, state: State<NotesState>) -> Result<Note, Str
                 );
                 note
             })
-            .ok_or_else(|| "Note not found".to_string())
+            .ok_or_else(|| note_not_found(&id))
     })
 }
 
+/// With `auto_complete` on, ticking off the last item by hand completes the
+/// task just as a checkbox command would.
 pub(crate) fn update_note(
     id: String,
     title: String,
     content: String,
-    state: State<NotesState>,
-) -> Result<Note, String> {
-    let notes = with_notes_state(&state, |notes_guard| Ok(notes_guard.clone()))?;
-
-    let (new_notes, mut updated) = find_and_update_note(notes, &id, |note| {
-        update_timestamp(Note {
-            title: title.clone(),
-            content: content.clone(),
-            ..note
-        })
-    })?;
+    expected_revision: Option<u64>,
+    states: &[StateMark],
+    auto_complete: bool,
+    state: &NotesState,
+) -> AppResult<Note> {
+    let auto_complete = auto_complete.then_some(states);
+    let mut updated =
+        update_note_and_complete(state, &id, expected_revision, auto_complete, |note| {
+            Ok(Note {
+                title: title.clone(),
+                content: content.clone(),
+                ..note
+            })
+        })?;
 
     // Add content_without_checkboxes
     updated.content_without_checkboxes = Some(
@@ -148,73 +604,694 @@ pub(crate) fn update_note(
             .to_string(),
     );
 
-    // Save to storage
-    storage::save_note(&updated)?;
+    Ok(updated)
+}
 
-    // Update in-memory state
-    modify_notes_state(&state, |state_notes| {
-        *state_notes = new_notes;
-        Ok(())
-    })?;
+pub(crate) fn note_exists(id: &str, state: &NotesState) -> AppResult<bool> {
+    with_notes_state(state, |notes_guard| {
+        Ok(find_in_tree(&notes_guard, id).is_some())
+    })
+}
 
-    Ok(updated)
+/// Credited by the timer rather than edited by a client, so only
+/// `updated_at` moves and the revision stays put; a save of the open note
+/// shouldn't conflict because a work phase ended meanwhile.
+pub(crate) fn increment_pomodoro_count(id: &str, state: &NotesState) -> AppResult<Note> {
+    modify_notes_state(state, |notes| {
+        let (new_notes, updated) = find_and_update_in_tree(notes.clone(), id, |note| Note {
+            pomodoro_count: note.pomodoro_count + 1,
+            updated_at: unix_now(),
+            ..note
+        })?;
+        storage::save_single_note(&state.dir, &updated)?;
+        *notes = new_notes;
+        Ok(updated)
+    })
 }
 
-pub(crate) fn delete_note(id: String, state: State<NotesState>) -> Result<(), String> {
-    // Delete from storage first
-    storage::delete_note(&id)?;
+pub(crate) fn create_child_note(
+    parent_id: String,
+    title: String,
+    content: String,
+    state: &NotesState,
+) -> AppResult<Note> {
+    modify_notes_state(state, |notes| {
+        let draft = Note::new(title, content);
+        let id = unique_id(draft.id.clone(), |candidate| {
+            find_in_tree(notes, candidate).is_some()
+        });
+        let child = Note {
+            parent_id: Some(parent_id.clone()),
+            ..with_id(draft, id)
+        };
 
-    // Update in-memory state functionally
-    modify_notes_state(&state, |notes| {
-        *notes = remove_note_from_list(std::mem::take(notes), &id);
-        Ok(())
-    })?;
+        let (new_notes, parent) =
+            find_and_update_in_tree(notes.clone(), &parent_id, |parent| Note {
+                children: add_note_to_list(parent.children, child.clone()),
+                ..parent
+            })?;
 
-    Ok(())
+        storage::save_single_note(&state.dir, &child)?;
+        storage::save_single_note(&state.dir, &parent)?;
+        state.index.lock()?.upsert(&child);
+        *notes = new_notes;
+        Ok(child)
+    })
+}
+
+/// Moves a note (with its subtree) under another note, or to the top level
+/// when `new_parent_id` is `None`. `index` defaults to the end of the list.
+pub(crate) fn move_note(
+    id: String,
+    new_parent_id: Option<String>,
+    index: Option<usize>,
+    state: &NotesState,
+) -> AppResult<Note> {
+    modify_notes_state(state, |notes| {
+        let note = find_in_tree(notes, &id)
+            .cloned()
+            .ok_or_else(|| note_not_found(&id))?;
+
+        if let Some(target) = new_parent_id.as_deref() {
+            if find_in_tree(std::slice::from_ref(&note), target).is_some() {
+                return Err(AppError::invalid_input(
+                    "Cannot move a note into itself or its children",
+                )
+                .with_context(&id));
+            }
+            if find_in_tree(notes, target).is_none() {
+                return Err(AppError::not_found("Parent note not found").with_context(target));
+            }
+        }
+
+        let old_parent_id = note.parent_id.clone();
+        let moved = touch(Note {
+            parent_id: new_parent_id.clone(),
+            ..note
+        });
+
+        let detached = remove_from_tree(notes.clone(), &id);
+        let new_notes = match new_parent_id.as_deref() {
+            Some(target) => {
+                find_and_update_in_tree(detached, target, |parent| Note {
+                    children: insert_note_at(parent.children, index, moved.clone()),
+                    ..parent
+                })?
+                .0
+            }
+            None => insert_note_at(detached, index, moved.clone()),
+        };
+
+        // Both parents' child lists changed; they're the same note on a reorder
+        storage::save_single_note(&state.dir, &moved)?;
+        [old_parent_id, new_parent_id]
+            .iter()
+            .flatten()
+            .filter_map(|parent_id| find_in_tree(&new_notes, parent_id))
+            .try_for_each(|parent| storage::save_single_note(&state.dir, parent))?;
+
+        *notes = new_notes;
+        Ok(moved)
+    })
 }
 
-pub(crate) fn load_all_notes(state: State<NotesState>) -> Result<Vec<Note>, String> {
-    let loaded_notes = storage::load_all_notes()?;
+/// Deletes a note together with all of its children.
+pub(crate) fn delete_note(id: String, state: &NotesState) -> AppResult<()> {
+    modify_notes_state(state, |notes| {
+        let note = find_in_tree(notes, &id)
+            .cloned()
+            .ok_or_else(|| note_not_found(&id))?;
+        let new_notes = remove_from_tree(notes.clone(), &id);
+
+        // Unlink from the parent first so a failure midway leaves stray
+        // children that load as top-level notes rather than dangling ids
+        if let Some(parent) = note
+            .parent_id
+            .as_deref()
+            .and_then(|parent_id| find_in_tree(&new_notes, parent_id))
+        {
+            storage::save_single_note(&state.dir, parent)?;
+        }
+        let ids = subtree_ids(&note);
+        ids.iter()
+            .rev()
+            .try_for_each(|id| storage::delete_note(&state.dir, id))?;
+
+        let mut index = state.index.lock()?;
+        ids.iter().for_each(|id| index.remove(id));
 
-    modify_notes_state(&state, |notes| {
-        *notes = loaded_notes.clone();
+        *notes = new_notes;
+        Ok(())
+    })
+}
+
+pub(crate) fn load_all_notes(state: &NotesState) -> AppResult<Vec<Note>> {
+    let scan = storage::load_all_notes(&state.dir)?;
+
+    *state.unreadable.lock()? = scan.unreadable;
+    *state.failures.lock()? = scan.failures;
+    modify_notes_state(state, |notes| {
+        *state.index.lock()? = SearchIndex::build(&scan.notes);
+        *notes = scan.notes.clone();
         Ok(())
     })?;
 
-    Ok(loaded_notes)
+    Ok(scan.notes)
+}
+
+pub(crate) fn get_unreadable_notes(state: &NotesState) -> AppResult<Vec<storage::UnreadableNote>> {
+    Ok(state.unreadable.lock()?.clone())
 }
 
-pub(crate) fn parse_checkboxes(content: String) -> Result<Vec<checkbox_parser::Checkbox>, String> {
-    Ok(checkbox_parser::parse_checkboxes(&content))
+pub(crate) fn get_scan_failures(state: &NotesState) -> AppResult<Vec<storage::ScanFailure>> {
+    Ok(state.failures.lock()?.clone())
+}
+
+pub(crate) fn parse_checkboxes(
+    content: String,
+    states: &[StateMark],
+) -> AppResult<Vec<checkbox_parser::Checkbox>> {
+    Ok(checkbox_parser::parse_checkboxes(&content, states))
+}
+
+pub(crate) fn get_checkbox_states(settings: &SettingsState) -> AppResult<Vec<StateMark>> {
+    settings::checkbox_states(settings)
+}
+
+/// Replaces the configured states; their order is the cycling order.
+pub(crate) fn update_checkbox_states(
+    states: Vec<StateMark>,
+    settings: &SettingsState,
+) -> AppResult<Vec<StateMark>> {
+    settings::update_settings(settings, |settings| Settings {
+        checkbox_states: states,
+        ..settings
+    })
+    .map(|settings| settings.checkbox_states)
+}
+
+pub(crate) fn get_auto_complete_tasks(settings: &SettingsState) -> AppResult<bool> {
+    settings::auto_complete_tasks(settings)
+}
+
+pub(crate) fn set_auto_complete_tasks(enabled: bool, settings: &SettingsState) -> AppResult<bool> {
+    settings::update_settings(settings, |settings| Settings {
+        auto_complete_tasks: enabled,
+        ..settings
+    })
+    .map(|settings| settings.auto_complete_tasks)
+}
+
+/// Marks a task done once all of its checkbox items (its children's
+/// included) are, or open again when one is reopened, then does the same
+/// for its ancestors, whose tallies take its items in. Goes through
+/// `set_task_status_in` so a recurring task still gets its next occurrence.
+fn complete_from_checkboxes(
+    notes: &mut Vec<Note>,
+    id: &str,
+    states: &[StateMark],
+    state: &NotesState,
+) -> AppResult<()> {
+    // Cycles among parent ids are kept as they are, so stop at a repeat
+    let mut seen = std::collections::HashSet::new();
+    let mut next_id = Some(id.to_string());
+    while let Some(id) = next_id.filter(|id| seen.insert(id.clone())) {
+        let note = find_in_tree(notes, &id).ok_or_else(|| note_not_found(&id))?;
+        let tally = note_tally(note, states);
+        next_id = note.parent_id.clone();
+        if note.is_task && tally.total > 0 && tally.is_complete() != note.is_done {
+            set_task_status_in(notes, state, &id, true, tally.is_complete())?;
+        }
+    }
+    Ok(())
+}
+
+/// Applies a checkbox edit to the note's content. Checkboxes are addressed
+/// by line, so clients should pass `expected_revision` to be sure the line
+/// still holds the item they mean.
+fn edit_note_checkboxes<F>(
+    note_id: &str,
+    expected_revision: Option<u64>,
+    auto_complete: Option<&[StateMark]>,
+    state: &NotesState,
+    edit: F,
+) -> AppResult<Note>
+where
+    F: FnOnce(&str) -> AppResult<String>,
+{
+    update_note_and_complete(state, note_id, expected_revision, auto_complete, |note| {
+        Ok(Note {
+            content: edit(&note.content).map_err(|e| e.with_context(note_id))?,
+            ..note
+        })
+    })
 }
 
 pub(crate) fn update_note_checkbox_status(
     note_id: String,
-    checkbox_text: String,
+    line: usize,
     new_status: bool,
-    state: State<NotesState>,
-) -> Result<Note, String> {
-    let notes = with_notes_state(&state, |notes_guard| Ok(notes_guard.clone()))?;
-
-    let (new_notes, updated) = find_and_update_note(notes, &note_id, |note| {
-        update_timestamp(Note {
-            content: checkbox_parser::update_checkbox_in_content(
-                &note.content,
-                &checkbox_text,
-                new_status,
-            ),
-            ..note
-        })
-    })?;
+    expected_revision: Option<u64>,
+    states: &[StateMark],
+    auto_complete: bool,
+    state: &NotesState,
+) -> AppResult<Note> {
+    let auto_complete = auto_complete.then_some(states);
+    edit_note_checkboxes(
+        &note_id,
+        expected_revision,
+        auto_complete,
+        state,
+        |content| checkbox_parser::set_checkbox_status(content, line, new_status, states),
+    )
+}
 
-    // Save to storage
-    storage::save_note(&updated)?;
+/// Open checkbox items across the vault, narrowed by their inline metadata.
+pub(crate) fn get_open_items(
+    filter: ItemFilter,
+    states: &[StateMark],
+    state: &NotesState,
+) -> AppResult<Vec<OpenItem>> {
+    let name = |name: Option<String>, sigil: char| {
+        name.map(|name| parse_tag(name.trim().trim_start_matches(sigil)))
+            .transpose()
+    };
+    let filter = ItemFilter {
+        context: name(filter.context, '@')?,
+        project: name(filter.project, '+')?,
+        ..filter
+    };
 
-    // Update in-memory state
-    modify_notes_state(&state, |state_notes| {
-        *state_notes = new_notes;
-        Ok(())
-    })?;
+    with_notes_state(state, |notes_guard| {
+        Ok(open_items::open_items(
+            &notes_guard,
+            &filter,
+            states,
+            due::today(),
+        ))
+    })
+}
 
-    Ok(updated)
+/// Moves a checkbox to the next of the configured states.
+pub(crate) fn cycle_note_checkbox(
+    note_id: String,
+    line: usize,
+    expected_revision: Option<u64>,
+    states: &[StateMark],
+    auto_complete: bool,
+    state: &NotesState,
+) -> AppResult<Note> {
+    let auto_complete = auto_complete.then_some(states);
+    edit_note_checkboxes(
+        &note_id,
+        expected_revision,
+        auto_complete,
+        state,
+        |content| checkbox_parser::cycle_checkbox_state(content, line, states),
+    )
+}
+
+pub(crate) fn edit_note_checkbox(
+    note_id: String,
+    line: usize,
+    text: String,
+    expected_revision: Option<u64>,
+    state: &NotesState,
+) -> AppResult<Note> {
+    edit_note_checkboxes(&note_id, expected_revision, None, state, |content| {
+        checkbox_parser::set_checkbox_text(content, line, &text)
+    })
+}
+
+pub(crate) fn delete_note_checkbox(
+    note_id: String,
+    line: usize,
+    expected_revision: Option<u64>,
+    states: &[StateMark],
+    auto_complete: bool,
+    state: &NotesState,
+) -> AppResult<Note> {
+    let auto_complete = auto_complete.then_some(states);
+    edit_note_checkboxes(
+        &note_id,
+        expected_revision,
+        auto_complete,
+        state,
+        |content| checkbox_parser::delete_checkbox(content, line),
+    )
+}
+
+/// Turns the checkbox on `line` into a child note with the item's text as
+/// its title, done if the item was. Sub-items become the child's content.
+/// Top-level items and children are ordered apart, so the child goes after
+/// the existing children.
+pub(crate) fn promote_checkbox(
+    note_id: String,
+    line: usize,
+    expected_revision: Option<u64>,
+    states: &[StateMark],
+    state: &NotesState,
+) -> AppResult<Note> {
+    modify_notes_state(state, |notes| {
+        check_revision(notes, &note_id, expected_revision)?;
+        let parent = find_in_tree(notes, &note_id).ok_or_else(|| note_not_found(&note_id))?;
+        let taken = checkbox_parser::take_checkbox(&parent.content, line, states)
+            .map_err(|e| e.with_context(&note_id))?;
+
+        let draft = Note::new(taken.checkbox.text.clone(), taken.body);
+        let id = unique_id(draft.id.clone(), |candidate| {
+            find_in_tree(notes, candidate).is_some()
+        });
+        let child = Note {
+            parent_id: Some(note_id.clone()),
+            is_task: true,
+            is_done: taken.checkbox.completed,
+            ..with_id(draft, id)
+        };
+
+        let (new_notes, parent) = find_and_update_in_tree(notes.clone(), &note_id, |parent| {
+            touch(Note {
+                content: taken.rest.clone(),
+                children: insert_note_at(parent.children, None, child.clone()),
+                ..parent
+            })
+        })?;
+
+        storage::save_single_note(&state.dir, &child)?;
+        storage::save_single_note(&state.dir, &parent)?;
+        let mut index = state.index.lock()?;
+        index.upsert(&child);
+        index.upsert(&parent);
+        *notes = new_notes;
+        Ok(child)
+    })
+}
+
+/// What a checkbox line has no room for, named for the error.
+fn lost_by_demoting(note: &Note) -> Vec<&'static str> {
+    [
+        (note.pomodoro_count > 0, "pomodoros"),
+        (!note.tags.is_empty(), "tags"),
+        (note.due.is_some(), "a due date"),
+        (note.scheduled.is_some(), "a scheduled date"),
+        (!note.reminders.is_empty(), "reminders"),
+        (note.recurrence.is_some(), "a recurrence"),
+    ]
+    .into_iter()
+    .filter(|(is_set, _)| *is_set)
+    .map(|(_, what)| what)
+    .collect()
+}
+
+/// Turns a child note back into a checkbox of its parent, placed among the
+/// top-level items where it stood among the children. Its content goes
+/// under the item. Notes with children of their own, or with anything the
+/// line would drop, are refused. Returns the parent.
+pub(crate) fn demote_note(
+    id: String,
+    expected_revision: Option<u64>,
+    states: &[StateMark],
+    state: &NotesState,
+) -> AppResult<Note> {
+    modify_notes_state(state, |notes| {
+        check_revision(notes, &id, expected_revision)?;
+        let note = find_in_tree(notes, &id).ok_or_else(|| note_not_found(&id))?;
+        if !note.children.is_empty() {
+            return Err(AppError::invalid_input(
+                "Cannot turn a note with child notes into a checkbox",
+            )
+            .with_context(&id));
+        }
+        let lost = lost_by_demoting(note);
+        if !lost.is_empty() {
+            return Err(AppError::invalid_input(format!(
+                "Cannot turn a note with {} into a checkbox",
+                lost.join(", ")
+            ))
+            .with_context(&id));
+        }
+        if note.title.trim().is_empty() {
+            return Err(
+                AppError::invalid_input("Cannot turn an untitled note into a checkbox")
+                    .with_context(&id),
+            );
+        }
+        let parent_id = note.parent_id.clone().ok_or_else(|| {
+            AppError::invalid_input("Only child notes can become checkboxes").with_context(&id)
+        })?;
+        let parent = find_in_tree(notes, &parent_id).ok_or_else(|| note_not_found(&parent_id))?;
+
+        let position = parent
+            .children
+            .iter()
+            .position(|child| child.id == id)
+            .unwrap_or(parent.children.len());
+        let content = checkbox_parser::insert_checkbox(
+            &parent.content,
+            position,
+            note.title.trim(),
+            note.is_done,
+            &note.content,
+            states,
+        );
+
+        let detached = remove_from_tree(notes.clone(), &id);
+        let (new_notes, parent) = find_and_update_in_tree(detached, &parent_id, |parent| {
+            touch(Note {
+                content: content.clone(),
+                ..parent
+            })
+        })?;
+
+        // Same order as `delete_note`: unlink from the parent first
+        storage::save_single_note(&state.dir, &parent)?;
+        storage::delete_note(&state.dir, &id)?;
+        let mut index = state.index.lock()?;
+        index.remove(&id);
+        index.upsert(&parent);
+        *notes = new_notes;
+        Ok(parent)
+    })
+}
+
+/// Moves a checkbox and its sub-items to `index` among its siblings.
+pub(crate) fn move_note_checkbox(
+    note_id: String,
+    line: usize,
+    index: usize,
+    expected_revision: Option<u64>,
+    state: &NotesState,
+) -> AppResult<Note> {
+    edit_note_checkboxes(&note_id, expected_revision, None, state, |content| {
+        checkbox_parser::move_checkbox(content, line, index)
+    })
+}
+
+fn run_parsed_query(
+    query: &Query,
+    states: &[StateMark],
+    state: &NotesState,
+) -> AppResult<Vec<NotePreview>> {
+    let today = due::today();
+    with_notes_state(state, |notes_guard| {
+        Ok(query
+            .run(all_notes(&notes_guard), states, today)
+            .into_iter()
+            .map(|note| note_to_preview(note, states))
+            .collect())
+    })
+}
+
+/// Notes anywhere in the tree matching a query such as
+/// `is:task -is:done tag:work due:<7d sort:pomodoros`.
+pub(crate) fn run_query(
+    query: String,
+    states: &[StateMark],
+    state: &NotesState,
+) -> AppResult<Vec<NotePreview>> {
+    run_parsed_query(&query.parse()?, states, state)
+}
+
+pub(crate) fn list_saved_queries(state: &NotesState) -> AppResult<Vec<SavedQuery>> {
+    storage::load_saved_queries(&state.dir)
+}
+
+/// Saves `query` under `name`, replacing any query already called that.
+pub(crate) fn save_query(
+    name: String,
+    query: String,
+    state: &NotesState,
+) -> AppResult<Vec<SavedQuery>> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(AppError::invalid_input("Query name cannot be empty"));
+    }
+    let query = query.trim().to_string();
+    query.parse::<Query>()?;
+
+    let _queries = state.queries.lock()?;
+    let mut saved = storage::load_saved_queries(&state.dir)?;
+    match saved.iter_mut().find(|saved| saved.name == name) {
+        Some(existing) => existing.query = query,
+        None => saved.push(SavedQuery { name, query }),
+    }
+    storage::save_saved_queries(&state.dir, &saved)?;
+    Ok(saved)
+}
+
+pub(crate) fn delete_saved_query(name: String, state: &NotesState) -> AppResult<Vec<SavedQuery>> {
+    let _queries = state.queries.lock()?;
+    let saved = storage::load_saved_queries(&state.dir)?;
+    if !saved.iter().any(|saved| saved.name == name) {
+        return Err(AppError::not_found("Saved query not found").with_context(&name));
+    }
+
+    let remaining: Vec<SavedQuery> = saved
+        .into_iter()
+        .filter(|saved| saved.name != name)
+        .collect();
+    storage::save_saved_queries(&state.dir, &remaining)?;
+    Ok(remaining)
+}
+
+pub(crate) fn run_saved_query(
+    name: String,
+    states: &[StateMark],
+    state: &NotesState,
+) -> AppResult<Vec<NotePreview>> {
+    let saved = storage::load_saved_queries(&state.dir)?
+        .into_iter()
+        .find(|saved| saved.name == name)
+        .ok_or_else(|| AppError::not_found("Saved query not found").with_context(&name))?;
+    run_parsed_query(&saved.query.parse()?, states, state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+    use std::fs;
+
+    fn scratch_state(name: &str) -> NotesState {
+        let dir = std::env::temp_dir().join(format!("tomatxt-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        NotesState::load(dir)
+    }
+
+    #[test]
+    fn test_stale_revision_is_refused() {
+        let state = scratch_state("revision");
+        let note = create_note("Plan".to_string(), "v1".to_string(), &state).unwrap();
+        let save = |content: &str, revision| {
+            update_note(
+                note.id.clone(),
+                "Plan".to_string(),
+                content.to_string(),
+                Some(revision),
+                &[],
+                false,
+                &state,
+            )
+        };
+
+        let saved = save("v2", note.revision).unwrap();
+        assert_eq!(saved.revision, note.revision + 1);
+
+        let stale = save("v3", note.revision).err().unwrap();
+        assert_eq!(stale.kind, ErrorKind::Conflict);
+        let on_disk = storage::load_all_notes(&state.dir).unwrap().notes;
+        assert_eq!(on_disk[0].content, "v2");
+
+        // The timer crediting a pomodoro doesn't make the open note stale,
+        // though it still counts as a change
+        state.notes.lock().unwrap()[0].updated_at = 0;
+        let credited = increment_pomodoro_count(&note.id, &state).unwrap();
+        assert_eq!(credited.revision, saved.revision);
+        assert!(credited.updated_at > 0);
+        let resaved = save("v3", saved.revision).unwrap();
+        assert_eq!(resaved.pomodoro_count, 1);
+        fs::remove_dir_all(&state.dir).unwrap();
+    }
+    #[test]
+    fn test_promote_and_demote_keep_children_and_details() {
+        let state = scratch_state("promote");
+        let states = checkbox_parser::default_states();
+        let trip = create_note(
+            "Trip".to_string(),
+            "- [ ] Visa\n- [ ] Hotel".to_string(),
+            &state,
+        )
+        .unwrap();
+        create_child_note(
+            trip.id.clone(),
+            "Tickets".to_string(),
+            String::new(),
+            &state,
+        )
+        .unwrap();
+
+        // The first item still lands after the existing child
+        let visa = promote_checkbox(trip.id.clone(), 0, None, &states, &state).unwrap();
+        let children = get_note(trip.id.clone(), &state).unwrap().children;
+        let titles: Vec<_> = children.iter().map(|child| child.title.as_str()).collect();
+        assert_eq!(titles, ["Tickets", "Visa"]);
+
+        increment_pomodoro_count(&visa.id, &state).unwrap();
+        let refused = demote_note(visa.id.clone(), None, &states, &state)
+            .err()
+            .unwrap();
+        assert_eq!(refused.kind, ErrorKind::InvalidInput);
+        assert_eq!(get_note(trip.id.clone(), &state).unwrap().children.len(), 2);
+        fs::remove_dir_all(&state.dir).unwrap();
+    }
+    #[test]
+    fn test_auto_complete_follows_content_saves_up_the_tree() {
+        let state = scratch_state("auto-complete");
+        let states = checkbox_parser::default_states();
+        let trip = create_note("Trip".to_string(), "- [ ] Pack".to_string(), &state).unwrap();
+        let visa = create_child_note(
+            trip.id.clone(),
+            "Visa".to_string(),
+            "- [ ] Apply".to_string(),
+            &state,
+        )
+        .unwrap();
+        for id in [&trip.id, &visa.id] {
+            set_task_status(id.clone(), true, false, None, &state).unwrap();
+        }
+
+        let trip =
+            update_note_checkbox_status(trip.id, 0, true, None, &states, true, &state).unwrap();
+        assert!(!trip.is_done);
+
+        let visa = get_note(visa.id, &state).unwrap();
+        let visa = update_note(
+            visa.id,
+            visa.title,
+            "- [x] Apply".to_string(),
+            Some(visa.revision),
+            &states,
+            true,
+            &state,
+        )
+        .unwrap();
+        assert!(visa.is_done);
+        assert!(get_note(trip.id, &state).unwrap().is_done);
+        fs::remove_dir_all(&state.dir).unwrap();
+    }
+    #[test]
+    fn test_concurrent_query_saves_are_all_kept() {
+        let state = scratch_state("queries");
+        std::thread::scope(|scope| {
+            for i in 0..8 {
+                let state = &state;
+                scope.spawn(move || {
+                    save_query(format!("q{}", i), "is:task".to_string(), state).unwrap();
+                });
+            }
+        });
+
+        assert_eq!(list_saved_queries(&state).unwrap().len(), 8);
+        fs::remove_dir_all(&state.dir).unwrap();
+    }
 }
@@ -0,0 +1,109 @@
+use super::model::Note;
+use crate::error::{AppError, AppResult};
+use chrono::{Days, Local, NaiveDate};
+use serde::Deserialize;
+
+/// Due and scheduled dates are plain local days, written `YYYY-MM-DD`.
+pub const DATE_FORMAT: &str = "%Y-%m-%d";
+const UPCOMING_DAYS: u64 = 7;
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DueWindow {
+    /// Due before today.
+    Overdue,
+    Today,
+    /// Due in the seven days after today.
+    Upcoming,
+}
+
+pub fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+pub fn parse_date(date: &str) -> AppResult<NaiveDate> {
+    NaiveDate::parse_from_str(date.trim(), DATE_FORMAT)
+        .map_err(|e| AppError::invalid_input(e.to_string()).with_context(date))
+}
+
+/// Checks a date coming from the frontend and writes it back canonically.
+pub fn validate_date(date: Option<String>) -> AppResult<Option<String>> {
+    date.filter(|date| !date.trim().is_empty())
+        .map(|date| parse_date(&date).map(|day| day.format(DATE_FORMAT).to_string()))
+        .transpose()
+}
+
+/// Hand-edited files may hold anything here, so a bad date counts as none.
+pub fn due_date(note: &Note) -> Option<NaiveDate> {
+    note.due.as_deref().and_then(|due| parse_date(due).ok())
+}
+
+/// Whether `note` is an unfinished task due within `window` of `today`.
+pub fn is_due_in(note: &Note, window: DueWindow, today: NaiveDate) -> bool {
+    if !note.is_task || note.is_done {
+        return false;
+    }
+    due_date(note).is_some_and(|due| in_window(due, window, today))
+}
+
+pub fn in_window(due: NaiveDate, window: DueWindow, today: NaiveDate) -> bool {
+    match window {
+        DueWindow::Overdue => due < today,
+        DueWindow::Today => due == today,
+        DueWindow::Upcoming => {
+            due > today && today.checked_add_days(Days::new(UPCOMING_DAYS)) >= Some(due)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(due: Option<&str>, is_done: bool) -> Note {
+        Note {
+            is_task: true,
+            is_done,
+            due: due.map(str::to_string),
+            ..Note::new("Task".to_string(), String::new())
+        }
+    }
+
+    #[test]
+    fn test_due_windows() {
+        let today = NaiveDate::from_ymd_opt(2024, 2, 28).unwrap();
+        let windows = |note: &Note| {
+            [DueWindow::Overdue, DueWindow::Today, DueWindow::Upcoming]
+                .into_iter()
+                .filter(|&window| is_due_in(note, window, today))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            windows(&task(Some("2024-02-27"), false)),
+            [DueWindow::Overdue]
+        );
+        assert_eq!(
+            windows(&task(Some("2024-02-28"), false)),
+            [DueWindow::Today]
+        );
+        assert_eq!(
+            windows(&task(Some("2024-03-06"), false)),
+            [DueWindow::Upcoming]
+        );
+        assert!(windows(&task(Some("2024-03-07"), false)).is_empty());
+        assert!(windows(&task(Some("2024-02-27"), true)).is_empty());
+        assert!(windows(&task(Some("someday"), false)).is_empty());
+        assert!(windows(&task(None, false)).is_empty());
+    }
+
+    #[test]
+    fn test_validate_date() {
+        assert_eq!(
+            validate_date(Some(" 2024-3-5 ".to_string())).unwrap(),
+            Some("2024-03-05".to_string())
+        );
+        assert_eq!(validate_date(Some(String::new())).unwrap(), None);
+        assert!(validate_date(Some("2024-02-30".to_string())).is_err());
+    }
+}
@@ -0,0 +1,218 @@
+use super::due;
+use super::model::Note;
+use super::tags;
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::cmp::Ordering;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    #[default]
+    CreatedAt,
+    UpdatedAt,
+    Title,
+    /// Notes without a due date come last either way.
+    Due,
+    Pomodoros,
+    /// Share of checkbox items done; notes without items come last.
+    Progress,
+    /// The order the notes are kept in, e.g. children as they were moved.
+    Position,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Which notes `get_notes` returns. Unset fields don't filter and ranges
+/// include both ends.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct NoteFilter {
+    pub is_task: Option<bool>,
+    pub is_done: Option<bool>,
+    pub tag: Option<String>,
+    /// `YYYY-MM-DD`.
+    pub due_from: Option<String>,
+    pub due_to: Option<String>,
+    /// Unix times.
+    pub created_from: Option<u64>,
+    pub created_to: Option<u64>,
+    pub updated_from: Option<u64>,
+    pub updated_to: Option<u64>,
+    /// Only the direct children of this note.
+    pub parent_id: Option<String>,
+    /// Look through the whole tree rather than the top-level notes only.
+    /// Has no effect together with `parent_id`.
+    pub include_children: bool,
+}
+
+/// A filtered, sorted page of notes.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct NoteQuery {
+    #[serde(flatten)]
+    pub filter: NoteFilter,
+    /// `Position` for the children of `parent_id`, `CreatedAt` otherwise.
+    pub sort: Option<SortKey>,
+    pub direction: SortDirection,
+    pub offset: usize,
+    /// `None` for every note from `offset` on.
+    pub limit: Option<usize>,
+}
+
+/// `NoteFilter` with its tag normalized and its days parsed.
+pub struct Criteria<'a> {
+    pub filter: &'a NoteFilter,
+    pub tag: Option<String>,
+    pub due_from: Option<NaiveDate>,
+    pub due_to: Option<NaiveDate>,
+}
+
+fn in_range<T: PartialOrd>(value: T, from: Option<T>, to: Option<T>) -> bool {
+    from.is_none_or(|from| value >= from) && to.is_none_or(|to| value <= to)
+}
+
+impl Criteria<'_> {
+    pub fn matches(&self, note: &Note) -> bool {
+        let filter = self.filter;
+        let due_range = self.due_from.is_some() || self.due_to.is_some();
+
+        filter.is_task.is_none_or(|is_task| note.is_task == is_task)
+            && filter.is_done.is_none_or(|is_done| note.is_done == is_done)
+            && self.tag.as_ref().is_none_or(|tag| tags::has_tag(note, tag))
+            && (!due_range
+                || due::due_date(note).is_some_and(|due| in_range(due, self.due_from, self.due_to)))
+            && in_range(note.created_at, filter.created_from, filter.created_to)
+            && in_range(note.updated_at, filter.updated_from, filter.updated_to)
+    }
+}
+
+fn directed(ordering: Ordering, direction: SortDirection) -> Ordering {
+    match direction {
+        SortDirection::Asc => ordering,
+        SortDirection::Desc => ordering.reverse(),
+    }
+}
+
+/// `None` sorts last whatever the direction.
+fn compare_present<T: Ord>(a: Option<T>, b: Option<T>, direction: SortDirection) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => directed(a.cmp(&b), direction),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+impl NoteQuery {
+    pub fn sort_key(&self) -> SortKey {
+        self.sort.unwrap_or(match self.filter.parent_id {
+            Some(_) => SortKey::Position,
+            None => SortKey::CreatedAt,
+        })
+    }
+}
+
+/// Sorts notes paired with their progress, which only `SortKey::Progress`
+/// looks at. Ties go by id, so the order never depends on how the notes
+/// were loaded; `SortKey::Position` keeps the given order instead.
+pub fn sort_notes(notes: &mut [(&Note, Option<u8>)], key: SortKey, direction: SortDirection) {
+    if key == SortKey::Position {
+        if direction == SortDirection::Desc {
+            notes.reverse();
+        }
+        return;
+    }
+
+    notes.sort_by(|(a, a_progress), (b, b_progress)| {
+        match key {
+            SortKey::CreatedAt => directed(a.created_at.cmp(&b.created_at), direction),
+            SortKey::UpdatedAt => directed(a.updated_at.cmp(&b.updated_at), direction),
+            SortKey::Title => directed(
+                a.title.to_lowercase().cmp(&b.title.to_lowercase()),
+                direction,
+            ),
+            SortKey::Due => compare_present(due::due_date(a), due::due_date(b), direction),
+            SortKey::Pomodoros => directed(a.pomodoro_count.cmp(&b.pomodoro_count), direction),
+            SortKey::Progress => compare_present(*a_progress, *b_progress, direction),
+            SortKey::Position => Ordering::Equal,
+        }
+        .then_with(|| a.id.cmp(&b.id))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(id: &str, title: &str, due: Option<&str>, pomodoros: u32) -> Note {
+        Note {
+            id: id.to_string(),
+            due: due.map(str::to_string),
+            pomodoro_count: pomodoros,
+            ..Note::new(title.to_string(), String::new())
+        }
+    }
+
+    fn ids(notes: &[(&Note, Option<u8>)]) -> Vec<String> {
+        notes.iter().map(|(note, _)| note.id.clone()).collect()
+    }
+
+    #[test]
+    fn test_sort_keeps_missing_values_last() {
+        let notes = [
+            note("a", "beta", Some("2024-03-02"), 1),
+            note("b", "Alpha", None, 3),
+            note("c", "gamma", Some("2024-03-01"), 3),
+        ];
+        let mut sorted: Vec<(&Note, Option<u8>)> =
+            notes.iter().zip([Some(50), None, Some(100)]).collect();
+
+        sort_notes(&mut sorted, SortKey::Due, SortDirection::Desc);
+        assert_eq!(ids(&sorted), ["a", "c", "b"]);
+        sort_notes(&mut sorted, SortKey::Title, SortDirection::Asc);
+        assert_eq!(ids(&sorted), ["b", "a", "c"]);
+        sort_notes(&mut sorted, SortKey::Pomodoros, SortDirection::Desc);
+        assert_eq!(ids(&sorted), ["b", "c", "a"]);
+        sort_notes(&mut sorted, SortKey::Progress, SortDirection::Asc);
+        assert_eq!(ids(&sorted), ["a", "c", "b"]);
+        sort_notes(&mut sorted, SortKey::Position, SortDirection::Desc);
+        assert_eq!(ids(&sorted), ["b", "c", "a"]);
+    }
+
+    #[test]
+    fn test_children_default_to_their_order() {
+        let mut query = NoteQuery::default();
+        assert_eq!(query.sort_key(), SortKey::CreatedAt);
+        query.filter.parent_id = Some("parent".to_string());
+        assert_eq!(query.sort_key(), SortKey::Position);
+        query.sort = Some(SortKey::Title);
+        assert_eq!(query.sort_key(), SortKey::Title);
+    }
+
+    #[test]
+    fn test_criteria_ranges() {
+        let filter = NoteFilter::default();
+        let criteria = Criteria {
+            filter: &filter,
+            tag: Some("work".to_string()),
+            due_from: due::parse_date("2024-03-01").ok(),
+            due_to: due::parse_date("2024-03-31").ok(),
+        };
+        let tagged = |note: Note| Note {
+            content: "#work".to_string(),
+            ..note
+        };
+
+        assert!(criteria.matches(&tagged(note("a", "A", Some("2024-03-31"), 0))));
+        assert!(!criteria.matches(&tagged(note("b", "B", Some("2024-04-01"), 0))));
+        assert!(!criteria.matches(&tagged(note("c", "C", None, 0))));
+        assert!(!criteria.matches(&note("d", "D", Some("2024-03-05"), 0)));
+    }
+}
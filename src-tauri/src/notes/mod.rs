@@ -2,19 +2,61 @@ pub mod commands;
 pub mod model;
 pub mod storage;
 pub mod checkbox_parser;
+pub mod due;
+pub mod listing;
+pub mod open_items;
+pub mod query;
+pub mod recurrence;
+pub mod reminders;
+pub mod search;
+pub mod tags;
+pub mod watcher;
 
 use model::Note;
+use notify::RecommendedWatcher;
+use search::SearchIndex;
+use std::path::PathBuf;
 use std::sync::Mutex;
+use storage::{NotesScan, ScanFailure, UnreadableNote};
 
+/// Emitted with an `AppError` when background work that no command is
+/// waiting on fails.
+pub const ERROR_EVENT: &str = "notes://error";
+
+/// Notes of one vault, held in memory while that vault is open.
 pub struct NotesState {
+    pub dir: PathBuf,
     pub notes: Mutex<Vec<Note>>,
+    /// Files the last scan couldn't load, kept so the UI can surface them.
+    pub unreadable: Mutex<Vec<UnreadableNote>>,
+    /// Files the last scan loaded but couldn't migrate or tidy up.
+    pub failures: Mutex<Vec<ScanFailure>>,
+    /// Full-text index over `notes`; lock it after `notes`, never before.
+    pub index: Mutex<SearchIndex>,
+    /// Set once `watcher::watch` runs; dropping it stops the watching.
+    pub watcher: Mutex<Option<RecommendedWatcher>>,
+    /// Held while the saved queries file is read, changed and written back.
+    pub queries: Mutex<()>,
 }
 
 impl NotesState {
-    pub fn new() -> Self {
-        let notes = storage::load_all_notes().unwrap_or_default();
+    pub fn load(dir: PathBuf) -> Self {
+        let scan = storage::load_all_notes(&dir).unwrap_or_else(|e| NotesScan {
+            notes: Vec::new(),
+            unreadable: Vec::new(),
+            failures: vec![ScanFailure {
+                path: dir.display().to_string(),
+                error: e,
+            }],
+        });
         Self {
-            notes: Mutex::new(notes),
+            dir,
+            index: Mutex::new(SearchIndex::build(&scan.notes)),
+            notes: Mutex::new(scan.notes),
+            unreadable: Mutex::new(scan.unreadable),
+            failures: Mutex::new(scan.failures),
+            watcher: Mutex::new(None),
+            queries: Mutex::new(()),
         }
     }
 }
@@ -1,7 +1,10 @@
+use super::checkbox_parser::{parse_checkboxes, StateMark, Tally};
+use super::tags::note_tags;
 use serde::{Deserialize, Serialize};
+use serde_yaml::Mapping;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Note {
     pub id: String,
     pub parent_id: Option<String>,
@@ -13,12 +16,38 @@ pub struct Note {
     pub is_task: bool,
     pub is_done: bool,
     pub pomodoro_count: u32,
+    /// Tags kept in the frontmatter; inline `#tags` stay in `content`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Day the task is due, `YYYY-MM-DD`.
+    #[serde(default)]
+    pub due: Option<String>,
+    /// Day work on the task is planned to start, `YYYY-MM-DD`.
+    #[serde(default)]
+    pub scheduled: Option<String>,
+    /// Unix times at which to remind about the note.
+    #[serde(default)]
+    pub reminders: Vec<u64>,
+    /// Rule for bringing the task back once done, e.g. `every weekday`.
+    #[serde(default)]
+    pub recurrence: Option<String>,
+    /// Whether the next occurrence starts with every checkbox unchecked.
+    #[serde(default)]
+    pub reset_checkboxes: bool,
 
     pub created_at: u64,
     pub updated_at: u64,
+    /// Bumped on every change; clients send back the one they last saw so
+    /// stale writes can be refused.
+    #[serde(default)]
+    pub revision: u64,
 
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub children: Vec<Note>,
+
+    /// Frontmatter keys this app doesn't know about, kept for round-trips.
+    #[serde(skip)]
+    pub extra_frontmatter: Mapping,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -29,13 +58,136 @@ pub struct NotePreview {
     pub content_preview: String,
     pub content_without_checkboxes: String,
     pub child_count: u32,
+    /// Checkbox items of the note and all notes under it, sub-items included.
+    pub checkbox_total: u32,
+    pub checkbox_completed: u32,
+    /// Share of those items done, `None` when there are none.
+    pub percent_complete: Option<u8>,
     pub is_task: bool,
     pub is_done: bool,
     pub pomodoro_count: u32,
+    /// Frontmatter and inline tags together, normalized.
+    pub tags: Vec<String>,
+    pub due: Option<String>,
+    pub scheduled: Option<String>,
+    pub recurrence: Option<String>,
     pub created_at: u64,
     pub updated_at: u64,
 }
 
+/// One page of `get_notes`; `total` counts every note that matched.
+#[derive(Clone, Serialize)]
+pub struct NotePage {
+    pub notes: Vec<NotePreview>,
+    pub total: usize,
+}
+
+/// Result of changing a task's status; `next` is the occurrence generated
+/// when a recurring task gets done.
+#[derive(Clone, Serialize)]
+pub struct TaskStatus {
+    pub note: Note,
+    pub next: Option<Note>,
+}
+
+const SLUG_MAX_LEN: usize = 32;
+
+/// Lowercase ASCII slug of a title, safe to use in a filename.
+pub fn slugify(title: &str) -> String {
+    let slug = title
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>()
+        .split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+
+    let slug = slug
+        .chars()
+        .take(SLUG_MAX_LEN)
+        .collect::<String>()
+        .trim_end_matches('-')
+        .to_string();
+
+    if slug.is_empty() {
+        "note".to_string()
+    } else {
+        slug
+    }
+}
+
+/// Ids double as filenames, so only characters that can't escape the notes
+/// directory or trip up a filesystem are allowed.
+pub fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 128
+        && id
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Replaces every disallowed character of a legacy id with `-`.
+pub fn sanitize_id(id: &str) -> String {
+    let sanitized = id
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .take(96)
+        .collect::<String>()
+        .trim_matches('-')
+        .to_string();
+
+    if sanitized.is_empty() {
+        "note".to_string()
+    } else {
+        sanitized
+    }
+}
+
+pub fn unique_id<F>(base: String, is_taken: F) -> String
+where
+    F: Fn(&str) -> bool,
+{
+    if !is_taken(&base) {
+        return base;
+    }
+
+    (2..)
+        .map(|n| format!("{}-{}", base, n))
+        .find(|candidate| !is_taken(candidate))
+        .unwrap_or(base)
+}
+
+/// Renames a note and points its children at the new id.
+pub fn with_id(note: Note, id: String) -> Note {
+    let children = note
+        .children
+        .into_iter()
+        .map(|child| Note {
+            parent_id: Some(id.clone()),
+            ..child
+        })
+        .collect();
+
+    Note {
+        id,
+        children,
+        ..note
+    }
+}
+
 fn is_checkbox_line(line: &str) -> bool {
     let trimmed = line.trim();
     trimmed.starts_with("- [") || trimmed.starts_with("* [")
@@ -63,9 +215,19 @@ fn create_preview(content_without_checkboxes: String) -> String {
     truncate_content(&content_without_checkboxes, 100)
 }
 
-pub fn note_to_preview(note: &Note, child_count: u32) -> NotePreview {
+/// Checkbox items of `note` and every note under it.
+pub fn note_tally(note: &Note, states: &[StateMark]) -> Tally {
+    note.children.iter().fold(
+        Tally::of(&parse_checkboxes(&note.content, states)),
+        |tally, child| tally.add(note_tally(child, states)),
+    )
+}
+
+/// `note` must come with its children for the counts to be right.
+pub fn note_to_preview(note: &Note, states: &[StateMark]) -> NotePreview {
     let content_without_checkboxes = remove_checkboxes(&note.content);
     let content_preview = create_preview(content_without_checkboxes.clone());
+    let tally = note_tally(note, states);
 
     NotePreview {
         id: note.id.clone(),
@@ -73,10 +235,17 @@ pub fn note_to_preview(note: &Note, child_count: u32) -> NotePreview {
         title: note.title.clone(),
         content_preview,
         content_without_checkboxes,
-        child_count,
+        child_count: note.children.len() as u32,
+        checkbox_total: tally.total,
+        checkbox_completed: tally.completed,
+        percent_complete: tally.percent(),
         is_task: note.is_task,
         is_done: note.is_done,
         pomodoro_count: note.pomodoro_count,
+        tags: note_tags(note),
+        due: note.due.clone(),
+        scheduled: note.scheduled.clone(),
+        recurrence: note.recurrence.clone(),
         created_at: note.created_at,
         updated_at: note.updated_at,
     }
@@ -84,16 +253,10 @@ pub fn note_to_preview(note: &Note, child_count: u32) -> NotePreview {
 
 impl Note {
     pub fn new(title: String, content: String) -> Self {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let now = since_epoch.as_secs();
 
-        let id = format!(
-            "{}-{}",
-            now,
-            title.chars().take(8).collect::<String>().replace(' ', "-")
-        );
+        let id = format!("{}-{}", since_epoch.as_millis(), slugify(&title));
 
         Self {
             id,
@@ -102,11 +265,19 @@ impl Note {
             content_without_checkboxes: None,
             created_at: now,
             updated_at: now,
+            revision: 0,
             parent_id: None,
             is_task: false,
             is_done: false,
             pomodoro_count: 0,
+            tags: Vec::new(),
+            due: None,
+            scheduled: None,
+            reminders: Vec::new(),
+            recurrence: None,
+            reset_checkboxes: false,
             children: Vec::new(),
+            extra_frontmatter: Mapping::new(),
         }
     }
 }
@@ -0,0 +1,161 @@
+use super::checkbox_parser::{flatten_checkboxes, parse_checkboxes, Checkbox, Priority, StateMark};
+use super::due::{self, DueWindow};
+use super::model::Note;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+
+/// Which open items to list. Unset fields don't filter; contexts and
+/// projects must already be normalized.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ItemFilter {
+    pub due: Option<DueWindow>,
+    /// Only items at least this urgent.
+    pub priority: Option<Priority>,
+    pub context: Option<String>,
+    pub project: Option<String>,
+}
+
+/// A checkbox that is neither done nor cancelled, with the note it is in.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct OpenItem {
+    pub note_id: String,
+    pub note_title: String,
+    /// The item alone; its sub-items are listed on their own.
+    pub checkbox: Checkbox,
+}
+
+fn matches(checkbox: &Checkbox, filter: &ItemFilter, today: NaiveDate) -> bool {
+    let meta = &checkbox.meta;
+    let due = meta
+        .due
+        .as_deref()
+        .and_then(|due| due::parse_date(due).ok());
+
+    filter
+        .due
+        .is_none_or(|window| due.is_some_and(|due| due::in_window(due, window, today)))
+        && filter
+            .priority
+            .is_none_or(|min| meta.priority.is_some_and(|priority| priority >= min))
+        && filter
+            .context
+            .as_ref()
+            .is_none_or(|context| meta.contexts.contains(context))
+        && filter
+            .project
+            .as_ref()
+            .is_none_or(|project| meta.projects.contains(project))
+}
+
+/// Open items of every note in the tree, children included. Items with a
+/// due date come first, soonest first, then the most urgent.
+pub fn open_items(
+    notes: &[Note],
+    filter: &ItemFilter,
+    states: &[StateMark],
+    today: NaiveDate,
+) -> Vec<OpenItem> {
+    fn collect(
+        notes: &[Note],
+        filter: &ItemFilter,
+        states: &[StateMark],
+        today: NaiveDate,
+        found: &mut Vec<OpenItem>,
+    ) {
+        for note in notes {
+            let checkboxes = parse_checkboxes(&note.content, states);
+            found.extend(
+                flatten_checkboxes(&checkboxes)
+                    .into_iter()
+                    .filter(|checkbox| !checkbox.state.is_closed())
+                    .filter(|checkbox| matches(checkbox, filter, today))
+                    .map(|checkbox| OpenItem {
+                        note_id: note.id.clone(),
+                        note_title: note.title.clone(),
+                        checkbox: Checkbox {
+                            children: Vec::new(),
+                            ..checkbox.clone()
+                        },
+                    }),
+            );
+            collect(&note.children, filter, states, today, found);
+        }
+    }
+
+    let mut found = Vec::new();
+    collect(notes, filter, states, today, &mut found);
+    found.sort_by_key(|item| {
+        let meta = &item.checkbox.meta;
+        (meta.due.is_none(), meta.due.clone(), Reverse(meta.priority))
+    });
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notes::checkbox_parser::default_states;
+
+    fn titles(notes: &[Note], filter: ItemFilter) -> Vec<String> {
+        let today = due::parse_date("2026-11-01").unwrap();
+        open_items(notes, &filter, &default_states(), today)
+            .into_iter()
+            .map(|item| item.checkbox.meta.title)
+            .collect()
+    }
+
+    #[test]
+    fn test_open_items_across_notes() {
+        let child = Note::new(
+            "Calls".to_string(),
+            "- [ ] vendor due:2026-11-02 !high @phone\n- [x] bank @phone".to_string(),
+        );
+        let parent = Note {
+            children: vec![child],
+            ..Note::new(
+                "Launch".to_string(),
+                "- [/] slides !low +launch\n  - [-] video +launch\n- [ ] venue due:2026-10-30"
+                    .to_string(),
+            )
+        };
+        let notes = [parent];
+
+        assert_eq!(
+            titles(&notes, ItemFilter::default()),
+            ["venue", "vendor", "slides"]
+        );
+        assert_eq!(
+            titles(
+                &notes,
+                ItemFilter {
+                    context: Some("phone".to_string()),
+                    ..ItemFilter::default()
+                }
+            ),
+            ["vendor"]
+        );
+        assert_eq!(
+            titles(
+                &notes,
+                ItemFilter {
+                    due: Some(DueWindow::Overdue),
+                    ..ItemFilter::default()
+                }
+            ),
+            ["venue"]
+        );
+        assert_eq!(
+            titles(
+                &notes,
+                ItemFilter {
+                    priority: Some(Priority::Low),
+                    project: Some("launch".to_string()),
+                    ..ItemFilter::default()
+                }
+            ),
+            ["slides"]
+        );
+    }
+}
@@ -0,0 +1,540 @@
+use super::checkbox_parser::{
+    flatten_checkboxes, parse_checkboxes, parse_priority, Checkbox, Priority, StateMark,
+};
+use super::due;
+use super::listing::{self, SortDirection, SortKey};
+use super::model::{note_tally, Note};
+use super::tags;
+use crate::error::{AppError, AppResult};
+use chrono::{Days, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// A query kept in the vault under a name, e.g. a "This week at work" smart
+/// list.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedQuery {
+    pub name: String,
+    pub query: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Comparison {
+    Less,
+    AtMost,
+    Equal,
+    AtLeast,
+    Greater,
+}
+
+impl Comparison {
+    /// Splits a leading `<`, `<=`, `=`, `>=` or `>` off `value`; none means
+    /// equal.
+    fn split(value: &str) -> (Comparison, &str) {
+        [
+            ("<=", Comparison::AtMost),
+            (">=", Comparison::AtLeast),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+            ("=", Comparison::Equal),
+        ]
+        .into_iter()
+        .find_map(|(prefix, comparison)| value.strip_prefix(prefix).map(|rest| (comparison, rest)))
+        .unwrap_or((Comparison::Equal, value))
+    }
+
+    fn holds<T: Ord>(self, value: T, target: T) -> bool {
+        match self {
+            Comparison::Less => value < target,
+            Comparison::AtMost => value <= target,
+            Comparison::Equal => value == target,
+            Comparison::AtLeast => value >= target,
+            Comparison::Greater => value > target,
+        }
+    }
+}
+
+/// A day in a query. Relative days are counted from the day the query runs,
+/// so a saved `due:<7d` keeps meaning "the coming week".
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Day {
+    Absolute(NaiveDate),
+    Relative(i64),
+}
+
+impl Day {
+    fn parse(text: &str) -> Option<Day> {
+        match text {
+            "today" => return Some(Day::Relative(0)),
+            "tomorrow" => return Some(Day::Relative(1)),
+            "yesterday" => return Some(Day::Relative(-1)),
+            _ => {}
+        }
+        if let Ok(date) = due::parse_date(text) {
+            return Some(Day::Absolute(date));
+        }
+
+        let (count, days_per_unit) = match text.strip_suffix('d') {
+            Some(count) => (count, 1),
+            None => (text.strip_suffix('w')?, 7),
+        };
+        count
+            .parse::<i64>()
+            .ok()
+            .and_then(|count| count.checked_mul(days_per_unit))
+            .map(Day::Relative)
+    }
+
+    fn resolve(self, today: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Day::Absolute(date) => Some(date),
+            Day::Relative(days) if days >= 0 => today.checked_add_days(Days::new(days as u64)),
+            Day::Relative(days) => today.checked_sub_days(Days::new(days.unsigned_abs())),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Term {
+    IsTask,
+    IsDone,
+    IsRecurring,
+    IsChild,
+    HasDue,
+    HasScheduled,
+    HasReminders,
+    HasChildren,
+    HasCheckboxes,
+    Tag(String),
+    Due(Comparison, Day),
+    Scheduled(Comparison, Day),
+    Pomodoros(Comparison, u32),
+    /// Percent of checkbox items done, sub-notes included.
+    Progress(Comparison, u8),
+    /// The rest match against the note's open checkbox items.
+    Context(String),
+    Project(String),
+    Priority(Comparison, Priority),
+    Title(String),
+    /// A bare word, looked for in the title and the content.
+    Text(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Condition {
+    negated: bool,
+    term: Term,
+}
+
+/// A parsed query such as `is:task -is:done tag:work due:<7d sort:pomodoros`.
+/// Every condition must hold; a leading `-` negates one, and double quotes
+/// keep a phrase together.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Query {
+    conditions: Vec<Condition>,
+    sort: Option<(SortKey, SortDirection)>,
+    limit: Option<usize>,
+}
+
+fn invalid_term(token: &str) -> AppError {
+    AppError::invalid_input("Invalid query term").with_context(token)
+}
+
+/// Splits on whitespace outside double quotes; the quotes themselves go.
+fn tokenize(query: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+
+    for c in query.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+fn parse_name(name: &str, token: &str) -> AppResult<String> {
+    tags::normalize(name).ok_or_else(|| invalid_term(token))
+}
+
+fn parse_flag(key: &str, value: &str) -> Option<Term> {
+    match (key, value) {
+        ("is", "task") => Some(Term::IsTask),
+        ("is", "done") => Some(Term::IsDone),
+        ("is", "recurring") => Some(Term::IsRecurring),
+        ("is", "child") => Some(Term::IsChild),
+        ("has", "due") => Some(Term::HasDue),
+        ("has", "scheduled") => Some(Term::HasScheduled),
+        ("has", "reminders") => Some(Term::HasReminders),
+        ("has", "children") => Some(Term::HasChildren),
+        ("has", "checkboxes") => Some(Term::HasCheckboxes),
+        _ => None,
+    }
+}
+
+fn parse_day_term(value: &str) -> Option<(Comparison, Day)> {
+    match value {
+        "overdue" => Some((Comparison::Less, Day::Relative(0))),
+        _ => {
+            let (comparison, day) = Comparison::split(value);
+            Day::parse(day).map(|day| (comparison, day))
+        }
+    }
+}
+
+/// `sort:key` or `sort:key:asc|desc`. Keys where more is usually wanted
+/// first (updated, pomodoros, progress) default to descending.
+fn parse_sort(value: &str) -> Option<(SortKey, SortDirection)> {
+    let (key, direction) = match value.split_once(':') {
+        Some((key, direction)) => (key, Some(direction)),
+        None => (value, None),
+    };
+    let key = match key {
+        "created" | "created_at" => SortKey::CreatedAt,
+        "updated" | "updated_at" => SortKey::UpdatedAt,
+        "title" => SortKey::Title,
+        "due" => SortKey::Due,
+        "pomodoros" => SortKey::Pomodoros,
+        "progress" => SortKey::Progress,
+        "position" => SortKey::Position,
+        _ => return None,
+    };
+    let direction = match direction {
+        Some("asc") => SortDirection::Asc,
+        Some("desc") => SortDirection::Desc,
+        Some(_) => return None,
+        None if matches!(
+            key,
+            SortKey::UpdatedAt | SortKey::Pomodoros | SortKey::Progress
+        ) =>
+        {
+            SortDirection::Desc
+        }
+        None => SortDirection::Asc,
+    };
+    Some((key, direction))
+}
+
+fn parse_compared<T: FromStr>(value: &str) -> Option<(Comparison, T)> {
+    let (comparison, number) = Comparison::split(value);
+    number.parse().ok().map(|number| (comparison, number))
+}
+
+fn parse_term(key: &str, value: &str, token: &str) -> AppResult<Term> {
+    let term = match key {
+        "is" | "has" => parse_flag(key, &value.to_lowercase()),
+        "tag" => Some(Term::Tag(parse_name(value, token)?)),
+        "context" => Some(Term::Context(parse_name(
+            value.trim_start_matches('@'),
+            token,
+        )?)),
+        "project" => Some(Term::Project(parse_name(
+            value.trim_start_matches('+'),
+            token,
+        )?)),
+        "due" => parse_day_term(value).map(|(comparison, day)| Term::Due(comparison, day)),
+        "scheduled" => {
+            parse_day_term(value).map(|(comparison, day)| Term::Scheduled(comparison, day))
+        }
+        "pomodoros" => {
+            parse_compared(value).map(|(comparison, count)| Term::Pomodoros(comparison, count))
+        }
+        "progress" => parse_compared(value.trim_end_matches('%'))
+            .filter(|&(_, percent)| percent <= 100)
+            .map(|(comparison, percent)| Term::Progress(comparison, percent)),
+        "priority" => {
+            let (comparison, level) = Comparison::split(value);
+            parse_priority(level).map(|priority| Term::Priority(comparison, priority))
+        }
+        "title" if !value.is_empty() => Some(Term::Title(value.to_lowercase())),
+        _ => None,
+    };
+    term.ok_or_else(|| invalid_term(token))
+}
+
+impl FromStr for Query {
+    type Err = AppError;
+
+    fn from_str(query: &str) -> AppResult<Self> {
+        let mut parsed = Query::default();
+
+        for token in tokenize(query) {
+            let (negated, body) = match token.strip_prefix('-') {
+                Some(rest) if !rest.is_empty() => (true, rest),
+                _ => (false, token.as_str()),
+            };
+
+            let term = if let Some(context) = body.strip_prefix('@') {
+                Term::Context(parse_name(context, &token)?)
+            } else if let Some(project) = body.strip_prefix('+') {
+                Term::Project(parse_name(project, &token)?)
+            } else {
+                match body.split_once(':') {
+                    // Words such as `10:30` are text, not an unknown key
+                    Some((key, value)) if key.chars().all(|c| c.is_ascii_alphabetic()) => {
+                        match key.to_lowercase().as_str() {
+                            "sort" if !negated => {
+                                parsed.sort = Some(
+                                    parse_sort(&value.to_lowercase())
+                                        .ok_or_else(|| invalid_term(&token))?,
+                                );
+                                continue;
+                            }
+                            "limit" if !negated => {
+                                parsed.limit =
+                                    Some(value.parse().map_err(|_| invalid_term(&token))?);
+                                continue;
+                            }
+                            key => parse_term(key, value, &token)?,
+                        }
+                    }
+                    _ => Term::Text(body.to_lowercase()),
+                }
+            };
+            parsed.conditions.push(Condition { negated, term });
+        }
+        Ok(parsed)
+    }
+}
+
+/// What a note is matched against, worked out once per note.
+struct Subject<'a> {
+    note: &'a Note,
+    tags: Vec<String>,
+    open_items: Vec<Checkbox>,
+    progress: Option<u8>,
+    has_checkboxes: bool,
+}
+
+impl<'a> Subject<'a> {
+    fn of(note: &'a Note, states: &[StateMark]) -> Subject<'a> {
+        let tally = note_tally(note, states);
+        Subject {
+            note,
+            tags: tags::note_tags(note),
+            open_items: flatten_checkboxes(&parse_checkboxes(&note.content, states))
+                .into_iter()
+                .filter(|checkbox| !checkbox.state.is_closed())
+                .cloned()
+                .collect(),
+            progress: tally.percent(),
+            has_checkboxes: tally.total > 0,
+        }
+    }
+}
+
+fn day_matches(date: Option<&str>, comparison: Comparison, day: Day, today: NaiveDate) -> bool {
+    date.and_then(|date| due::parse_date(date).ok())
+        .zip(day.resolve(today))
+        .is_some_and(|(date, target)| comparison.holds(date, target))
+}
+
+impl Term {
+    fn matches(&self, subject: &Subject, today: NaiveDate) -> bool {
+        let note = subject.note;
+        match self {
+            Term::IsTask => note.is_task,
+            Term::IsDone => note.is_done,
+            Term::IsRecurring => note.recurrence.is_some(),
+            Term::IsChild => note.parent_id.is_some(),
+            Term::HasDue => due::due_date(note).is_some(),
+            Term::HasScheduled => note.scheduled.is_some(),
+            Term::HasReminders => !note.reminders.is_empty(),
+            Term::HasChildren => !note.children.is_empty(),
+            Term::HasCheckboxes => subject.has_checkboxes,
+            Term::Tag(tag) => subject.tags.contains(tag),
+            Term::Due(comparison, day) => {
+                day_matches(note.due.as_deref(), *comparison, *day, today)
+            }
+            Term::Scheduled(comparison, day) => {
+                day_matches(note.scheduled.as_deref(), *comparison, *day, today)
+            }
+            Term::Pomodoros(comparison, count) => comparison.holds(note.pomodoro_count, *count),
+            Term::Progress(comparison, percent) => subject
+                .progress
+                .is_some_and(|progress| comparison.holds(progress, *percent)),
+            Term::Context(context) => subject
+                .open_items
+                .iter()
+                .any(|item| item.meta.contexts.contains(context)),
+            Term::Project(project) => subject
+                .open_items
+                .iter()
+                .any(|item| item.meta.projects.contains(project)),
+            Term::Priority(comparison, priority) => subject.open_items.iter().any(|item| {
+                item.meta
+                    .priority
+                    .is_some_and(|level| comparison.holds(level, *priority))
+            }),
+            Term::Title(text) => note.title.to_lowercase().contains(text),
+            Term::Text(text) => {
+                note.title.to_lowercase().contains(text)
+                    || note.content.to_lowercase().contains(text)
+            }
+        }
+    }
+}
+
+impl Query {
+    /// The notes among `notes` that match, sorted and cut to the limit.
+    /// Without a `sort:` they keep the `get_notes` default order.
+    pub fn run<'a>(
+        &self,
+        notes: Vec<&'a Note>,
+        states: &[StateMark],
+        today: NaiveDate,
+    ) -> Vec<&'a Note> {
+        let (key, direction) = self.sort.unwrap_or_default();
+        let mut matched: Vec<(&Note, Option<u8>)> = notes
+            .into_iter()
+            .map(|note| Subject::of(note, states))
+            .filter(|subject| {
+                self.conditions
+                    .iter()
+                    .all(|condition| condition.term.matches(subject, today) != condition.negated)
+            })
+            .map(|subject| (subject.note, subject.progress))
+            .collect();
+        listing::sort_notes(&mut matched, key, direction);
+
+        matched
+            .into_iter()
+            .take(self.limit.unwrap_or(usize::MAX))
+            .map(|(note, _)| note)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notes::checkbox_parser::default_states;
+
+    fn date(text: &str) -> NaiveDate {
+        due::parse_date(text).unwrap()
+    }
+
+    fn note(id: &str, content: &str) -> Note {
+        Note {
+            id: id.to_string(),
+            ..Note::new(id.to_string(), content.to_string())
+        }
+    }
+
+    fn run(query: &str, notes: &[Note]) -> Vec<String> {
+        query
+            .parse::<Query>()
+            .unwrap()
+            .run(
+                notes.iter().collect(),
+                &default_states(),
+                date("2024-03-01"),
+            )
+            .into_iter()
+            .map(|note| note.id.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_queries() {
+        let query: Query = r#"is:task -is:done "weekly review" due:<7d sort:pomodoros limit:5"#
+            .parse()
+            .unwrap();
+        assert_eq!(
+            query.conditions,
+            [
+                Condition {
+                    negated: false,
+                    term: Term::IsTask
+                },
+                Condition {
+                    negated: true,
+                    term: Term::IsDone
+                },
+                Condition {
+                    negated: false,
+                    term: Term::Text("weekly review".to_string())
+                },
+                Condition {
+                    negated: false,
+                    term: Term::Due(Comparison::Less, Day::Relative(7))
+                },
+            ]
+        );
+        assert_eq!(query.sort, Some((SortKey::Pomodoros, SortDirection::Desc)));
+        assert_eq!(query.limit, Some(5));
+        assert!("meeting 10:30".parse::<Query>().is_ok());
+
+        for query in [
+            "is:urgent",
+            "due:someday",
+            "sort:size",
+            "-sort:due",
+            "limit:many",
+            "progress:>150",
+            "colour:red",
+        ] {
+            assert!(query.parse::<Query>().is_err(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn test_run_queries() {
+        let notes = [
+            Note {
+                is_task: true,
+                tags: vec!["work".to_string()],
+                due: Some("2024-03-04".to_string()),
+                pomodoro_count: 2,
+                ..note("soon", "- [ ] call @phone !high\n- [x] draft")
+            },
+            Note {
+                is_task: true,
+                due: Some("2024-03-20".to_string()),
+                pomodoro_count: 5,
+                ..note("later", "#work\n- [ ] plan +launch")
+            },
+            Note {
+                is_task: true,
+                is_done: true,
+                tags: vec!["work".to_string()],
+                due: Some("2024-03-02".to_string()),
+                ..note("finished", "")
+            },
+            note("idea", "- [x] @phone someone"),
+        ];
+
+        assert_eq!(
+            run("is:task -is:done tag:work due:<7d sort:pomodoros", &notes),
+            ["soon"]
+        );
+        assert_eq!(
+            run("tag:work sort:pomodoros", &notes),
+            ["later", "soon", "finished"]
+        );
+        assert_eq!(
+            run("tag:work sort:due limit:2", &notes),
+            ["finished", "soon"]
+        );
+        assert_eq!(run("due:overdue", &notes), Vec::<String>::new());
+        assert_eq!(
+            run("due:>=2024-03-04 -has:checkboxes", &notes),
+            Vec::<String>::new()
+        );
+        // Only open items count for checkbox metadata
+        assert_eq!(run("@phone", &notes), ["soon"]);
+        assert_eq!(run("+launch priority:>=low", &notes), Vec::<String>::new());
+        assert_eq!(run("priority:high progress:50", &notes), ["soon"]);
+        assert_eq!(run("PLAN -title:soon", &notes), ["later"]);
+    }
+}
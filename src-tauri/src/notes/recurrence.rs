@@ -0,0 +1,319 @@
+use super::checkbox_parser::reset_checkboxes;
+use super::due::{self, DATE_FORMAT};
+use super::model::Note;
+use crate::error::{AppError, AppResult};
+use chrono::{Datelike, Days, Months, NaiveDate, Weekday};
+use std::fmt;
+use std::str::FromStr;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unit {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ordinal {
+    First,
+    Second,
+    Third,
+    Fourth,
+    Last,
+}
+
+/// When a recurring task comes back, written in frontmatter as plain text
+/// such as `every weekday`, `every 2 weeks` or `every first monday of the
+/// month`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Recurrence {
+    Every {
+        interval: u32,
+        unit: Unit,
+    },
+    /// Monday to Friday.
+    Weekdays,
+    MonthlyOn {
+        ordinal: Ordinal,
+        weekday: Weekday,
+    },
+}
+
+fn parse_unit(word: &str) -> Option<Unit> {
+    match word.trim_end_matches('s') {
+        "day" => Some(Unit::Day),
+        "week" => Some(Unit::Week),
+        "month" => Some(Unit::Month),
+        "year" => Some(Unit::Year),
+        _ => None,
+    }
+}
+
+fn parse_ordinal(word: &str) -> Option<Ordinal> {
+    match word {
+        "first" | "1st" => Some(Ordinal::First),
+        "second" | "2nd" => Some(Ordinal::Second),
+        "third" | "3rd" => Some(Ordinal::Third),
+        "fourth" | "4th" => Some(Ordinal::Fourth),
+        "last" => Some(Ordinal::Last),
+        _ => None,
+    }
+}
+
+fn invalid_rule(rule: &str) -> AppError {
+    AppError::invalid_input("Unknown recurrence rule").with_context(rule)
+}
+
+impl FromStr for Recurrence {
+    type Err = AppError;
+
+    fn from_str(rule: &str) -> AppResult<Self> {
+        let lowered = rule.trim().to_lowercase();
+        let words: Vec<&str> = lowered.split_whitespace().collect();
+        let every = |interval, unit| Recurrence::Every { interval, unit };
+
+        let parsed = match words.as_slice() {
+            ["daily"] => Some(every(1, Unit::Day)),
+            ["weekly"] => Some(every(1, Unit::Week)),
+            ["monthly"] => Some(every(1, Unit::Month)),
+            ["yearly"] | ["annually"] => Some(every(1, Unit::Year)),
+            ["every", "weekday" | "weekdays"] | ["weekdays"] => Some(Recurrence::Weekdays),
+            ["every", "other", unit] => parse_unit(unit).map(|unit| every(2, unit)),
+            ["every", count, unit] if parse_unit(unit).is_some() => count
+                .parse()
+                .ok()
+                .filter(|&interval| interval > 0)
+                .zip(parse_unit(unit))
+                .map(|(interval, unit)| every(interval, unit)),
+            ["every", ordinal, weekday]
+            | ["every", ordinal, weekday, "of", "the", "month"]
+            | ["every", ordinal, weekday, "of", "month"] => parse_ordinal(ordinal)
+                .zip(weekday.parse::<Weekday>().ok())
+                .map(|(ordinal, weekday)| Recurrence::MonthlyOn { ordinal, weekday }),
+            ["every", unit] => parse_unit(unit).map(|unit| every(1, unit)),
+            _ => None,
+        };
+        parsed.ok_or_else(|| invalid_rule(rule))
+    }
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Recurrence::Every { interval, unit } => {
+                let unit = match unit {
+                    Unit::Day => "day",
+                    Unit::Week => "week",
+                    Unit::Month => "month",
+                    Unit::Year => "year",
+                };
+                match interval {
+                    1 => write!(f, "every {}", unit),
+                    n => write!(f, "every {} {}s", n, unit),
+                }
+            }
+            Recurrence::Weekdays => write!(f, "every weekday"),
+            Recurrence::MonthlyOn { ordinal, weekday } => {
+                let ordinal = match ordinal {
+                    Ordinal::First => "first",
+                    Ordinal::Second => "second",
+                    Ordinal::Third => "third",
+                    Ordinal::Fourth => "fourth",
+                    Ordinal::Last => "last",
+                };
+                let weekday = match weekday {
+                    Weekday::Mon => "monday",
+                    Weekday::Tue => "tuesday",
+                    Weekday::Wed => "wednesday",
+                    Weekday::Thu => "thursday",
+                    Weekday::Fri => "friday",
+                    Weekday::Sat => "saturday",
+                    Weekday::Sun => "sunday",
+                };
+                write!(f, "every {} {} of the month", ordinal, weekday)
+            }
+        }
+    }
+}
+
+/// The `ordinal` `weekday` in the month of `day`, if that month has one.
+fn weekday_of_month(day: NaiveDate, ordinal: Ordinal, weekday: Weekday) -> Option<NaiveDate> {
+    let (year, month) = (day.year(), day.month());
+    match ordinal {
+        Ordinal::First => NaiveDate::from_weekday_of_month_opt(year, month, weekday, 1),
+        Ordinal::Second => NaiveDate::from_weekday_of_month_opt(year, month, weekday, 2),
+        Ordinal::Third => NaiveDate::from_weekday_of_month_opt(year, month, weekday, 3),
+        Ordinal::Fourth => NaiveDate::from_weekday_of_month_opt(year, month, weekday, 4),
+        Ordinal::Last => NaiveDate::from_weekday_of_month_opt(year, month, weekday, 5)
+            .or_else(|| NaiveDate::from_weekday_of_month_opt(year, month, weekday, 4)),
+    }
+}
+
+fn first_of_month(day: NaiveDate) -> Option<NaiveDate> {
+    day.with_day(1)
+}
+
+impl Recurrence {
+    /// The first occurrence strictly after `day`. Month steps keep the day of
+    /// the month where they can and fall back to the month's last day.
+    pub fn next_after(&self, day: NaiveDate) -> Option<NaiveDate> {
+        match *self {
+            Recurrence::Every { interval, unit } => match unit {
+                Unit::Day => day.checked_add_days(Days::new(interval.into())),
+                Unit::Week => day.checked_add_days(Days::new(u64::from(interval) * 7)),
+                Unit::Month => day.checked_add_months(Months::new(interval)),
+                Unit::Year => day.checked_add_months(Months::new(interval.checked_mul(12)?)),
+            },
+            Recurrence::Weekdays => day
+                .iter_days()
+                .skip(1)
+                .find(|next| !matches!(next.weekday(), Weekday::Sat | Weekday::Sun)),
+            Recurrence::MonthlyOn { ordinal, weekday } => weekday_of_month(day, ordinal, weekday)
+                .filter(|&this_month| this_month > day)
+                .or_else(|| {
+                    let next_month = first_of_month(day)?.checked_add_months(Months::new(1))?;
+                    weekday_of_month(next_month, ordinal, weekday)
+                }),
+        }
+    }
+}
+
+/// Checks a rule coming from the frontend and writes it back canonically.
+pub fn validate_rule(rule: Option<String>) -> AppResult<Option<String>> {
+    rule.filter(|rule| !rule.trim().is_empty())
+        .map(|rule| rule.parse::<Recurrence>().map(|rule| rule.to_string()))
+        .transpose()
+}
+
+/// The next occurrence of a recurring task that was just completed, ready to
+/// be given an id. Its due date moves past both the old one and `today`, and
+/// the scheduled date and reminders move along by the same number of days.
+/// `None` if the note has no usable rule.
+pub fn next_occurrence(note: &Note, today: NaiveDate) -> Option<Note> {
+    let rule: Recurrence = note.recurrence.as_deref()?.parse().ok()?;
+    let due = due::due_date(note);
+
+    let mut next_due = rule.next_after(due.unwrap_or(today))?;
+    while next_due <= today {
+        next_due = rule.next_after(next_due)?;
+    }
+    let shift = (next_due - due.unwrap_or(today)).num_days();
+    let shift_date = |date: &String| {
+        due::parse_date(date).ok().and_then(|date| {
+            date.checked_add_days(Days::new(shift.try_into().ok()?))
+                .map(|date| date.format(DATE_FORMAT).to_string())
+        })
+    };
+
+    let content = if note.reset_checkboxes {
+        reset_checkboxes(&note.content)
+    } else {
+        note.content.clone()
+    };
+
+    Some(Note {
+        due: Some(next_due.format(DATE_FORMAT).to_string()),
+        scheduled: note.scheduled.as_ref().and_then(shift_date),
+        reminders: note
+            .reminders
+            .iter()
+            .filter_map(|&at| at.checked_add_signed(shift * SECONDS_PER_DAY))
+            .collect(),
+        is_task: true,
+        recurrence: note.recurrence.clone(),
+        reset_checkboxes: note.reset_checkboxes,
+        tags: note.tags.clone(),
+        parent_id: note.parent_id.clone(),
+        extra_frontmatter: note.extra_frontmatter.clone(),
+        ..Note::new(note.title.clone(), content)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(text: &str) -> NaiveDate {
+        due::parse_date(text).unwrap()
+    }
+
+    fn next(rule: &str, day: &str) -> String {
+        rule.parse::<Recurrence>()
+            .unwrap()
+            .next_after(date(day))
+            .unwrap()
+            .format(DATE_FORMAT)
+            .to_string()
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        for (rule, canonical) in [
+            ("daily", "every day"),
+            ("Every 2 Weeks", "every 2 weeks"),
+            ("every other month", "every 2 months"),
+            ("weekdays", "every weekday"),
+            (
+                "every 1st mon of the month",
+                "every first monday of the month",
+            ),
+            ("every last friday", "every last friday of the month"),
+        ] {
+            let parsed: Recurrence = rule.parse().unwrap();
+            assert_eq!(parsed.to_string(), canonical);
+            assert_eq!(canonical.parse::<Recurrence>().unwrap(), parsed);
+        }
+
+        for rule in ["every 0 days", "sometimes", "every fifth monday", "every"] {
+            assert!(rule.parse::<Recurrence>().is_err(), "{}", rule);
+        }
+    }
+
+    #[test]
+    fn test_next_after() {
+        assert_eq!(next("every 3 days", "2024-02-28"), "2024-03-02");
+        assert_eq!(next("every 2 weeks", "2024-02-28"), "2024-03-13");
+        assert_eq!(next("monthly", "2024-01-31"), "2024-02-29");
+        assert_eq!(next("yearly", "2024-02-29"), "2025-02-28");
+        assert_eq!(next("every weekday", "2024-03-01"), "2024-03-04");
+        assert_eq!(next("every first monday", "2024-03-01"), "2024-03-04");
+        assert_eq!(next("every first monday", "2024-03-04"), "2024-04-01");
+        assert_eq!(next("every last friday", "2024-03-29"), "2024-04-26");
+    }
+
+    #[test]
+    fn test_next_occurrence() {
+        let done = Note {
+            is_task: true,
+            is_done: true,
+            pomodoro_count: 4,
+            due: Some("2024-03-01".to_string()),
+            scheduled: Some("2024-02-28".to_string()),
+            reminders: vec![1_000],
+            recurrence: Some("every week".to_string()),
+            reset_checkboxes: true,
+            ..Note::new(
+                "Review".to_string(),
+                "- [x] inbox\n- [/] notes\n- [X] plan".to_string(),
+            )
+        };
+
+        // Two weeks late: skip the occurrences already in the past
+        let next = next_occurrence(&done, date("2024-03-15")).unwrap();
+        assert_eq!(next.due.as_deref(), Some("2024-03-22"));
+        assert_eq!(next.scheduled.as_deref(), Some("2024-03-20"));
+        assert_eq!(next.reminders, [1_000 + 21 * SECONDS_PER_DAY as u64]);
+        assert_eq!(next.content, "- [ ] inbox\n- [ ] notes\n- [ ] plan");
+        assert!(next.is_task && !next.is_done);
+        assert_eq!(next.pomodoro_count, 0);
+
+        let once = Note {
+            recurrence: None,
+            ..done
+        };
+        assert!(next_occurrence(&once, date("2024-03-15")).is_none());
+    }
+}
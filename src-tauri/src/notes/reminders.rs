@@ -0,0 +1,184 @@
+use super::model::Note;
+use super::storage::write_atomic;
+use super::ERROR_EVENT;
+use crate::error::{AppError, AppResult, ResultExt};
+use crate::vaults::VaultsState;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager};
+
+pub const REMINDER_EVENT: &str = "notes://reminder";
+const REMINDER_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Payload of `notes://reminder`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Reminder {
+    pub note_id: String,
+    pub title: String,
+    pub remind_at: u64,
+    pub due: Option<String>,
+}
+
+/// How far a vault's reminders have been handed out, so a restart or a
+/// switch back neither repeats them nor drops the ones that came due while
+/// the vault was closed.
+#[derive(Default, Serialize, Deserialize)]
+struct ReminderLog {
+    fired_until: u64,
+}
+
+/// Kept next to the notes like the saved queries; the scan only looks at
+/// `.md` files.
+fn get_log_path(notes_dir: &Path) -> PathBuf {
+    notes_dir.join("reminders.json")
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs())
+        .unwrap_or(0)
+}
+
+fn load_fired_until(notes_dir: &Path) -> AppResult<u64> {
+    let path = get_log_path(notes_dir);
+    if !path.exists() {
+        // First run: old reminders are history, not news
+        return Ok(now());
+    }
+
+    let log: ReminderLog = fs::read_to_string(&path)
+        .context(path.display())
+        .and_then(|content| serde_json::from_str(&content).context(path.display()))?;
+    Ok(log.fired_until)
+}
+
+fn save_fired_until(notes_dir: &Path, fired_until: u64) -> AppResult<()> {
+    let content = serde_json::to_string_pretty(&ReminderLog { fired_until })?;
+    write_atomic(&get_log_path(notes_dir), &content)
+}
+
+/// The watermark of the vault checked last, reloaded once another is open.
+struct Watermark {
+    dir: PathBuf,
+    fired_until: u64,
+}
+
+/// Reminders of unfinished notes set for after `after` and up to `until`,
+/// earliest first.
+pub fn due_reminders(notes: &[Note], after: u64, until: u64) -> Vec<Reminder> {
+    fn collect(notes: &[Note], after: u64, until: u64, found: &mut Vec<Reminder>) {
+        for note in notes {
+            if !note.is_done {
+                found.extend(
+                    note.reminders
+                        .iter()
+                        .filter(|&&at| at > after && at <= until)
+                        .map(|&remind_at| Reminder {
+                            note_id: note.id.clone(),
+                            title: note.title.clone(),
+                            remind_at,
+                            due: note.due.clone(),
+                        }),
+                );
+            }
+            collect(&note.children, after, until, found);
+        }
+    }
+
+    let mut found = Vec::new();
+    collect(notes, after, until, &mut found);
+    found.sort_by_key(|reminder| reminder.remind_at);
+    found
+}
+
+/// Only moves the watermark on once the window is handled, so a failed
+/// check is retried rather than skipped.
+fn fire_reminders(app: &AppHandle, watermark: &mut Option<Watermark>, until: u64) -> AppResult<()> {
+    let notes = app.state::<VaultsState>().active()?;
+    let after = match watermark {
+        Some(mark) if mark.dir == notes.dir => mark.fired_until,
+        _ => load_fired_until(&notes.dir)?,
+    };
+
+    let reminders = due_reminders(&notes.notes.lock()?, after, until);
+    if !reminders.is_empty() {
+        for reminder in reminders {
+            let _ = app.emit(REMINDER_EVENT, reminder);
+        }
+        save_fired_until(&notes.dir, until)?;
+    }
+
+    *watermark = Some(Watermark {
+        dir: notes.dir.clone(),
+        fired_until: after.max(until),
+    });
+    Ok(())
+}
+
+/// Checks the open vault for reminders every few seconds and emits one
+/// `notes://reminder` event for each that comes due.
+pub fn spawn_reminder_task(app: AppHandle) {
+    thread::spawn(move || {
+        let mut watermark = None;
+        let mut last_error: Option<AppError> = None;
+        loop {
+            match fire_reminders(&app, &mut watermark, now()) {
+                Ok(()) => last_error = None,
+                // Reported once, not on every retry
+                Err(e) if last_error.as_ref() != Some(&e) => {
+                    let _ = app.emit(ERROR_EVENT, e.clone());
+                    last_error = Some(e);
+                }
+                Err(_) => {}
+            }
+            thread::sleep(REMINDER_INTERVAL);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(title: &str, reminders: &[u64], is_done: bool) -> Note {
+        Note {
+            reminders: reminders.to_vec(),
+            is_done,
+            ..Note::new(title.to_string(), String::new())
+        }
+    }
+
+    #[test]
+    fn test_due_reminders_window() {
+        let parent = Note {
+            children: vec![note("Child", &[150], false)],
+            ..note("Parent", &[100, 300], false)
+        };
+        let notes = [parent, note("Done", &[120], true)];
+
+        let due: Vec<(String, u64)> = due_reminders(&notes, 100, 200)
+            .into_iter()
+            .map(|reminder| (reminder.title, reminder.remind_at))
+            .collect();
+        assert_eq!(due, [("Child".to_string(), 150)]);
+        assert_eq!(due_reminders(&notes, 99, 100).len(), 1);
+    }
+
+    #[test]
+    fn test_fired_until_is_kept_per_vault() {
+        let root = std::env::temp_dir().join(format!("tomatxt-reminders-{}", std::process::id()));
+        let (work, home) = (root.join("work"), root.join("home"));
+        fs::create_dir_all(&work).unwrap();
+        fs::create_dir_all(&home).unwrap();
+
+        save_fired_until(&work, 100).unwrap();
+        assert_eq!(load_fired_until(&work).unwrap(), 100);
+        // A vault that never fired starts from now, not from another's log
+        assert!(load_fired_until(&home).unwrap() > 100);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
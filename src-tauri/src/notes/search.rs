@@ -0,0 +1,354 @@
+use super::checkbox_parser::checkbox_text;
+use super::model::{Note, NotePreview};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+const SNIPPET_RADIUS: usize = 60;
+const MAX_SNIPPETS: usize = 3;
+/// Query words shorter than this only match whole terms, not prefixes.
+const MIN_PREFIX_LEN: usize = 2;
+const PREFIX_PENALTY: f64 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    Title,
+    Body,
+    Checkbox,
+}
+
+impl Field {
+    fn weight(self) -> f64 {
+        match self {
+            Field::Title => 3.0,
+            Field::Checkbox => 1.5,
+            Field::Body => 1.0,
+        }
+    }
+}
+
+/// Start and end of a highlighted match, in UTF-16 code units so the
+/// frontend can `slice` the snippet text directly.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Highlight {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Snippet {
+    pub field: Field,
+    pub text: String,
+    pub highlights: Vec<Highlight>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct SearchHit {
+    pub note: NotePreview,
+    pub score: f64,
+    pub snippets: Vec<Snippet>,
+}
+
+/// Lowercased words of `text` with their byte ranges.
+fn tokenize(text: &str) -> Vec<(String, usize, usize)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in text
+        .char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+    {
+        match (start, c.is_alphanumeric()) {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                tokens.push((text[s..i].to_lowercase(), s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    tokens
+}
+
+/// Splits a note into the text of each field, line by line.
+fn fields(note: &Note) -> Vec<(Field, String)> {
+    std::iter::once((Field::Title, note.title.clone()))
+        .chain(note.content.lines().map(|line| match checkbox_text(line) {
+            Some(text) => (Field::Checkbox, text.to_string()),
+            None => (Field::Body, line.to_string()),
+        }))
+        .collect()
+}
+
+/// Term frequencies of one note, per field.
+type DocTerms = HashMap<String, HashMap<Field, u32>>;
+
+fn doc_terms(note: &Note) -> DocTerms {
+    let mut terms = DocTerms::new();
+    for (field, text) in fields(note) {
+        for (term, _, _) in tokenize(&text) {
+            *terms.entry(term).or_default().entry(field).or_default() += 1;
+        }
+    }
+    terms
+}
+
+/// Inverted index over titles, body text and checkbox items, kept next to
+/// the notes and updated whenever one of them changes.
+#[derive(Default)]
+pub struct SearchIndex {
+    /// Sorted so prefix lookups are a range scan.
+    terms: BTreeMap<String, HashSet<String>>,
+    docs: HashMap<String, DocTerms>,
+}
+
+impl SearchIndex {
+    /// Indexes every note in the tree, children included.
+    pub fn build(notes: &[Note]) -> Self {
+        let mut index = Self::default();
+        index.upsert_tree(notes);
+        index
+    }
+
+    fn upsert_tree(&mut self, notes: &[Note]) {
+        for note in notes {
+            self.upsert(note);
+            self.upsert_tree(&note.children);
+        }
+    }
+
+    /// (Re)indexes a single note; its children are left alone.
+    pub fn upsert(&mut self, note: &Note) {
+        self.remove(&note.id);
+        let terms = doc_terms(note);
+        for term in terms.keys() {
+            self.terms
+                .entry(term.clone())
+                .or_default()
+                .insert(note.id.clone());
+        }
+        self.docs.insert(note.id.clone(), terms);
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        let Some(terms) = self.docs.remove(id) else {
+            return;
+        };
+        for term in terms.keys() {
+            if let Some(ids) = self.terms.get_mut(term) {
+                ids.remove(id);
+                if ids.is_empty() {
+                    self.terms.remove(term);
+                }
+            }
+        }
+    }
+
+    /// Indexed terms a query word matches, with how well they match.
+    fn expand<'a>(&'a self, word: &'a str) -> Vec<(&'a String, f64)> {
+        if word.chars().count() < MIN_PREFIX_LEN {
+            return self
+                .terms
+                .get_key_value(word)
+                .map(|(term, _)| vec![(term, 1.0)])
+                .unwrap_or_default();
+        }
+
+        self.terms
+            .range(word.to_string()..)
+            .take_while(|(term, _)| term.starts_with(word))
+            .map(|(term, _)| (term, if term == word { 1.0 } else { PREFIX_PENALTY }))
+            .collect()
+    }
+
+    fn idf(&self, term: &str) -> f64 {
+        let df = self.terms.get(term).map_or(0, HashSet::len) as f64;
+        (1.0 + self.docs.len() as f64 / (1.0 + df)).ln()
+    }
+
+    /// Ids of notes containing every query word, best match first.
+    pub fn search(&self, query: &str) -> Vec<(String, f64)> {
+        let words: Vec<String> = tokenize(query)
+            .into_iter()
+            .map(|(word, _, _)| word)
+            .collect();
+        if words.is_empty() {
+            return Vec::new();
+        }
+
+        let mut scores: Option<HashMap<&String, f64>> = None;
+        for word in &words {
+            let mut word_scores: HashMap<&String, f64> = HashMap::new();
+            for (term, closeness) in self.expand(word) {
+                let idf = self.idf(term);
+                for id in &self.terms[term] {
+                    let weighted: f64 = self.docs[id][term]
+                        .iter()
+                        .map(|(field, count)| field.weight() * f64::from(*count))
+                        .sum();
+                    *word_scores.entry(id).or_default() += closeness * idf * weighted;
+                }
+            }
+
+            // Every word has to match somewhere in the note
+            scores = Some(match scores {
+                None => word_scores,
+                Some(previous) => previous
+                    .into_iter()
+                    .filter_map(|(id, score)| word_scores.get(id).map(|s| (id, score + s)))
+                    .collect(),
+            });
+        }
+
+        let mut ranked: Vec<(String, f64)> = scores
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(id, score)| (id.clone(), score))
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        ranked
+    }
+}
+
+fn matches_word(term: &str, words: &[String]) -> bool {
+    words.iter().any(|word| {
+        term == word || (word.chars().count() >= MIN_PREFIX_LEN && term.starts_with(word.as_str()))
+    })
+}
+
+fn utf16_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+fn floor_char_boundary(text: &str, index: usize) -> usize {
+    (0..=index.min(text.len()))
+        .rev()
+        .find(|&i| text.is_char_boundary(i))
+        .unwrap_or(0)
+}
+
+fn ceil_char_boundary(text: &str, index: usize) -> usize {
+    (index.min(text.len())..=text.len())
+        .find(|&i| text.is_char_boundary(i))
+        .unwrap_or(text.len())
+}
+
+/// Cuts long body lines down to a window around the first match.
+fn snippet(field: Field, text: &str, ranges: &[(usize, usize)]) -> Snippet {
+    let (from, to) = match field {
+        Field::Body => (
+            floor_char_boundary(text, ranges[0].0.saturating_sub(SNIPPET_RADIUS)),
+            ceil_char_boundary(text, ranges[0].1 + SNIPPET_RADIUS),
+        ),
+        _ => (0, text.len()),
+    };
+    let window = &text[from..to];
+
+    let highlights = ranges
+        .iter()
+        .filter(|(start, end)| *start >= from && *end <= to)
+        .map(|(start, end)| Highlight {
+            start: utf16_len(&text[from..*start]),
+            end: utf16_len(&text[from..*end]),
+        })
+        .collect();
+
+    let ellipsis = |cut: bool| if cut { "…" } else { "" };
+    let prefix = ellipsis(from > 0);
+    Snippet {
+        field,
+        text: format!("{}{}{}", prefix, window, ellipsis(to < text.len())),
+        highlights: shift(highlights, utf16_len(prefix)),
+    }
+}
+
+fn shift(highlights: Vec<Highlight>, by: usize) -> Vec<Highlight> {
+    highlights
+        .into_iter()
+        .map(|h| Highlight {
+            start: h.start + by,
+            end: h.end + by,
+        })
+        .collect()
+}
+
+/// The first few lines of `note` that contain a query word.
+pub fn snippets(note: &Note, query: &str) -> Vec<Snippet> {
+    let words: Vec<String> = tokenize(query)
+        .into_iter()
+        .map(|(word, _, _)| word)
+        .collect();
+
+    fields(note)
+        .into_iter()
+        .filter_map(|(field, text)| {
+            let ranges: Vec<(usize, usize)> = tokenize(&text)
+                .into_iter()
+                .filter(|(term, _, _)| matches_word(term, &words))
+                .map(|(_, start, end)| (start, end))
+                .collect();
+            (!ranges.is_empty()).then(|| snippet(field, &text, &ranges))
+        })
+        .take(MAX_SNIPPETS)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(id: &str, title: &str, content: &str) -> Note {
+        Note {
+            id: id.to_string(),
+            ..Note::new(title.to_string(), content.to_string())
+        }
+    }
+
+    fn ids(results: Vec<(String, f64)>) -> Vec<String> {
+        results.into_iter().map(|(id, _)| id).collect()
+    }
+
+    #[test]
+    fn test_title_matches_rank_first() {
+        let index = SearchIndex::build(&[
+            note("body", "Groceries", "remember the budget"),
+            note("title", "Budget review", "numbers"),
+            note("task", "Chores", "- [ ] send budget"),
+        ]);
+
+        assert_eq!(ids(index.search("budget")), ["title", "task", "body"]);
+    }
+
+    #[test]
+    fn test_all_words_and_prefixes_must_match() {
+        let index = SearchIndex::build(&[
+            note("a", "Design review", "website mockups"),
+            note("b", "Design", "logo"),
+        ]);
+
+        assert_eq!(ids(index.search("desig web")), ["a"]);
+        assert!(index.search("d").is_empty());
+    }
+
+    #[test]
+    fn test_incremental_updates() {
+        let mut index = SearchIndex::build(&[note("a", "Alpha", "")]);
+        index.upsert(&note("a", "Beta", ""));
+        assert!(index.search("alpha").is_empty());
+        assert_eq!(ids(index.search("beta")), ["a"]);
+
+        index.remove("a");
+        assert!(index.search("beta").is_empty());
+        assert!(index.terms.is_empty());
+    }
+
+    #[test]
+    fn test_snippet_highlights_use_utf16_offsets() {
+        let found = snippets(&note("a", "Tomat 🍅 budget", "- [x] Budget"), "budget");
+
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].field, Field::Title);
+        assert_eq!(found[0].highlights, [Highlight { start: 9, end: 15 }]);
+        assert_eq!(found[1].field, Field::Checkbox);
+        assert_eq!(found[1].text, "Budget");
+    }
+}
@@ -1,14 +1,23 @@
-use super::model::Note;
+use super::model::{is_valid_id, sanitize_id, unique_id, with_id, Note};
+use super::query::SavedQuery;
+use super::tags;
+use crate::error::{AppError, AppResult, ResultExt};
+use rayon::iter::Either;
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Mapping;
+use std::collections::{HashMap, HashSet};
 use std::env;
-use std::fs;
-use std::path::PathBuf;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-fn home_dir_error(e: env::VarError) -> String {
-    format!("Could not find home directory: {}", e)
+fn home_dir_error(e: env::VarError) -> AppError {
+    AppError::not_found(format!("Could not find home directory: {}", e))
 }
 
-fn get_home_dir() -> Result<String, String> {
+fn get_home_dir() -> AppResult<String> {
     env::var("HOME")
         .or_else(get_userprofile)
         .map_err(home_dir_error)
@@ -18,255 +27,1180 @@ fn get_userprofile(_: env::VarError) -> Result<String, env::VarError> {
     env::var("USERPROFILE")
 }
 
-fn create_notes_dir(notes_dir: &PathBuf) -> Result<(), String> {
+pub(crate) fn create_notes_dir(notes_dir: &Path) -> AppResult<()> {
     if !notes_dir.exists() {
-        fs::create_dir_all(notes_dir).map_err(|e| e.to_string())?;
+        fs::create_dir_all(notes_dir).context(notes_dir.display())?;
     }
     Ok(())
 }
 
-fn get_notes_dir() -> Result<PathBuf, String> {
-    let home = get_home_dir()?;
-    let notes_dir = PathBuf::from(home).join(".tomatxt").join("notes");
+pub(crate) fn get_app_dir() -> AppResult<PathBuf> {
+    let app_dir = PathBuf::from(get_home_dir()?).join(".tomatxt");
+    create_notes_dir(&app_dir)?;
+    Ok(app_dir)
+}
+
+/// Built-in location of the default vault.
+pub(crate) fn get_notes_dir() -> AppResult<PathBuf> {
+    let notes_dir = get_app_dir()?.join("notes");
     create_notes_dir(&notes_dir)?;
     Ok(notes_dir)
 }
 
-fn get_note_path(id: &str) -> Result<PathBuf, String> {
-    get_notes_dir().map(|dir| dir.join(format!("{}.md", id)))
-}
+fn get_note_path(notes_dir: &Path, id: &str) -> AppResult<PathBuf> {
+    if !is_valid_id(id) {
+        return Err(AppError::invalid_input("Invalid note id").with_context(id));
+    }
 
-fn extract_field(metadata: &str, key: &str) -> Option<String> {
-    metadata
-        .lines()
-        .find(|line| line.trim().starts_with(key))
-        .and_then(|line| line.split_once(':'))
-        .map(|(_, val)| val.trim().to_string())
-}
-
-fn build_frontmatter(note: &Note) -> String {
-    vec![
-        format!("id: {}", note.id),
-        format!("is_task: {}", note.is_task),
-        format!("is_done: {}", note.is_done),
-        format!("pomodoro_count: {}", note.pomodoro_count),
-        format!("title: {}", note.title),
-        format!("created_at: {}", note.created_at),
-        format!("updated_at: {}", note.updated_at),
-    ]
-    .join("\n")
-}
-
-fn serialize_child_note(child: &Note, indent: usize) -> String {
-    let prefix = " ".repeat(indent);
-    let indented_content = if child.content.is_empty() {
-        String::new()
-    } else {
-        format!(
-            "\n{}{}",
-            prefix,
-            child.content.replace('\n', &format!("\n{}", prefix))
-        )
-    };
+    let path = notes_dir.join(format!("{}.md", id));
+    if path.parent() != Some(notes_dir) {
+        return Err(
+            AppError::invalid_input("Note id resolves outside the notes directory")
+                .with_context(id),
+        );
+    }
+    Ok(path)
+}
 
-    let nested_children = serialize_children(&child.children, indent + 2);
-    let nested_section = if nested_children.is_empty() {
-        String::new()
-    } else {
-        format!("\n{}", nested_children)
-    };
+/// A note file a scan couldn't load.
+#[derive(Clone, Debug, Serialize)]
+pub struct UnreadableNote {
+    pub path: String,
+    pub error: AppError,
+}
 
-    format!(
-        "{}---\n{}id: {}\n{}is_task: {}\n{}is_done: {}\n{}pomodoro_count: {}\n{}title: {}\n{}created_at: {}\n{}updated_at: {}\n{}---{}{}",
-        prefix, prefix, child.id, prefix, child.is_task, prefix, child.is_done, prefix,
-        child.pomodoro_count, prefix, child.title, prefix, child.created_at, prefix,
-        child.updated_at, prefix, indented_content, nested_section
-    )
+/// A file the scan loaded but had to leave as it was, such as an old note it
+/// couldn't migrate or a stale temp file it couldn't remove.
+#[derive(Clone, Debug, Serialize)]
+pub struct ScanFailure {
+    pub path: String,
+    pub error: AppError,
 }
 
-fn serialize_children(children: &[Note], indent: usize) -> String {
-    children
-        .iter()
-        .map(|child| serialize_child_note(child, indent))
-        .collect::<Vec<_>>()
-        .join("\n")
+impl ScanFailure {
+    fn new(path: &Path, error: AppError) -> Self {
+        Self {
+            path: path.display().to_string(),
+            error,
+        }
+    }
 }
 
-fn build_note_content(note: &Note) -> String {
-    let children_section = if note.children.is_empty() {
-        String::new()
-    } else {
-        format!("\n\n{}", serialize_children(&note.children, 0))
-    };
+pub struct NotesScan {
+    pub notes: Vec<Note>,
+    pub unreadable: Vec<UnreadableNote>,
+    pub failures: Vec<ScanFailure>,
+}
 
-    format!(
-        "---\n{}\n---\n\n{}{}",
-        build_frontmatter(note),
-        note.content,
-        children_section
-    )
+/// A YAML scalar read as the text it was written as, so a hand-written
+/// `title: 2024` or `tags: [work, 2024]` still loads.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum Scalar {
+    Text(String),
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
 }
 
-fn split_metadata_and_content(content: &str) -> Result<(&str, &str), String> {
-    let parts: Vec<&str> = content.splitn(3, "---").collect();
-    match parts.as_slice() {
-        [_, metadata, rest] => Ok((metadata, rest)),
-        _ => Err("Invalid note format".to_string()),
+impl Scalar {
+    pub(crate) fn into_text(self) -> String {
+        match self {
+            Scalar::Text(text) => text,
+            Scalar::Integer(n) => n.to_string(),
+            Scalar::Float(x) => x.to_string(),
+            Scalar::Bool(b) => b.to_string(),
+        }
     }
 }
 
-fn extract_fields(metadata: &str) -> Result<Note, String> {
-    let get_field = |key: &str| extract_field(metadata, key);
+fn deserialize_text<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Option::<Scalar>::deserialize(deserializer)
+        .map(|scalar| scalar.map(Scalar::into_text).unwrap_or_default())
+}
+
+#[derive(Serialize, Deserialize)]
+struct Frontmatter {
+    id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parent_id: Option<String>,
+    #[serde(default)]
+    is_task: bool,
+    #[serde(default)]
+    is_done: bool,
+    #[serde(default)]
+    pomodoro_count: u32,
+    #[serde(
+        default,
+        deserialize_with = "tags::deserialize_tags",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    due: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scheduled: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    reminders: Vec<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    recurrence: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    reset_checkboxes: bool,
+    #[serde(default, deserialize_with = "deserialize_text")]
+    title: String,
+    #[serde(default)]
+    created_at: u64,
+    #[serde(default)]
+    updated_at: u64,
+    #[serde(default)]
+    revision: u64,
+    /// Ids of the direct children, in display order. Each child lives in
+    /// its own file and points back here through `parent_id`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    children: Vec<String>,
+    /// Keys written by other tools, carried through untouched.
+    #[serde(flatten)]
+    extra: Mapping,
+}
 
-    Ok(Note {
-        id: get_field("id").ok_or("Missing ID")?,
-        parent_id: get_field("parent_id"),
-        title: get_field("title").unwrap_or_default(),
+fn to_frontmatter(note: &Note) -> Frontmatter {
+    Frontmatter {
+        id: note.id.clone(),
+        parent_id: note.parent_id.clone(),
+        is_task: note.is_task,
+        is_done: note.is_done,
+        pomodoro_count: note.pomodoro_count,
+        tags: note.tags.clone(),
+        due: note.due.clone(),
+        scheduled: note.scheduled.clone(),
+        reminders: note.reminders.clone(),
+        recurrence: note.recurrence.clone(),
+        reset_checkboxes: note.reset_checkboxes,
+        title: note.title.clone(),
+        created_at: note.created_at,
+        updated_at: note.updated_at,
+        revision: note.revision,
+        children: note.children.iter().map(|child| child.id.clone()).collect(),
+        extra: note.extra_frontmatter.clone(),
+    }
+}
+
+fn from_frontmatter(frontmatter: Frontmatter) -> (Note, Vec<String>) {
+    let note = Note {
+        id: frontmatter.id,
+        parent_id: frontmatter.parent_id,
+        title: frontmatter.title,
         content: String::new(),
         content_without_checkboxes: None,
-        is_task: get_field("is_task")
-            .and_then(|v| v.parse().ok())
-            .unwrap_or(false),
-        is_done: get_field("is_done")
-            .and_then(|v| v.parse().ok())
-            .unwrap_or(false),
-        pomodoro_count: get_field("pomodoro_count")
-            .and_then(|v| v.parse().ok())
-            .unwrap_or(0),
-        created_at: get_field("created_at")
-            .and_then(|v| v.parse().ok())
-            .unwrap_or(0),
-        updated_at: get_field("updated_at")
-            .and_then(|v| v.parse().ok())
-            .unwrap_or(0),
+        is_task: frontmatter.is_task,
+        is_done: frontmatter.is_done,
+        pomodoro_count: frontmatter.pomodoro_count,
+        tags: frontmatter.tags,
+        due: frontmatter.due,
+        scheduled: frontmatter.scheduled,
+        reminders: frontmatter.reminders,
+        recurrence: frontmatter.recurrence,
+        reset_checkboxes: frontmatter.reset_checkboxes,
+        created_at: frontmatter.created_at,
+        updated_at: frontmatter.updated_at,
+        revision: frontmatter.revision,
         children: Vec::new(),
-    })
+        extra_frontmatter: frontmatter.extra,
+    };
+    (note, frontmatter.children)
+}
+
+fn build_frontmatter(note: &Note) -> AppResult<String> {
+    serde_yaml::to_string(&to_frontmatter(note)).context(&note.id)
 }
 
-fn find_nested_section(content: &str) -> (String, Option<String>) {
-    content
-        .find("\n---\n")
-        .map(|pos| {
-            let before_dash = &content[..pos];
-            let last_newline = before_dash.rfind('\n').unwrap_or(0);
-            let line_before = &before_dash[last_newline..];
+fn parse_field<T: FromStr>(fields: &HashMap<&str, &str>, key: &str) -> Option<T> {
+    fields.get(key).and_then(|v| v.parse().ok())
+}
 
-            let is_separator = line_before.chars().all(|c| c.is_whitespace() || c == '\n');
+/// The only keys files from before YAML frontmatter could hold.
+const LEGACY_KEYS: [&str; 8] = [
+    "id",
+    "parent_id",
+    "is_task",
+    "is_done",
+    "pomodoro_count",
+    "title",
+    "created_at",
+    "updated_at",
+];
 
-            if is_separator {
-                (
-                    content[..pos].trim().to_string(),
-                    Some(content[pos + 1..].to_string()),
-                )
-            } else {
-                (content.trim().to_string(), None)
-            }
-        })
-        .unwrap_or_else(|| (content.trim().to_string(), None))
-}
-
-fn parse_nested_notes(children_str: &str, parent_id: &str) -> Result<Vec<Note>, String> {
-    fn parse_recursive(content: &str, parent_id: &str) -> Result<Vec<Note>, String> {
-        content.find("---\n").map_or(Ok(vec![]), |pos| {
-            let child_section = &content[pos + 4..];
-
-            child_section.find("\n---\n").map_or_else(
-                || {
-                    parse_note_file(
-                        &format!("---\n{}---\n", child_section),
-                        Some(parent_id.to_string()),
-                    )
-                    .map(|child| vec![child])
-                    .or(Ok(vec![]))
-                },
-                |end_pos| {
-                    let child_content = format!("---\n{}---\n", &child_section[..end_pos]);
-                    let remaining = &child_section[end_pos..];
-
-                    parse_note_file(&child_content, Some(parent_id.to_string()))
-                        .and_then(|child| {
-                            parse_recursive(remaining, parent_id)
-                                .map(|rest| [vec![child], rest].concat())
-                        })
-                        .or_else(|_| parse_recursive(remaining, parent_id))
-                },
-            )
+/// Whether every line is a plain `key: value` with a key the old format
+/// wrote. Anything else is YAML, and the line-by-line reader would drop it.
+fn is_legacy_frontmatter(metadata: &str) -> bool {
+    metadata
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .all(|line| {
+            line.split_once(':')
+                .is_some_and(|(key, _)| LEGACY_KEYS.contains(&key))
         })
+}
+
+/// Files written before frontmatter was real YAML contain unquoted values
+/// like `title: Meeting: Q3`, so fall back to reading them line by line.
+fn parse_legacy_frontmatter(metadata: &str) -> AppResult<Frontmatter> {
+    let fields: HashMap<&str, &str> = metadata
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, val)| (key.trim(), val.trim()))
+        .collect();
+
+    Ok(Frontmatter {
+        id: fields
+            .get("id")
+            .ok_or_else(|| AppError::parse("Missing ID"))?
+            .to_string(),
+        parent_id: fields.get("parent_id").map(|v| v.to_string()),
+        is_task: parse_field(&fields, "is_task").unwrap_or(false),
+        is_done: parse_field(&fields, "is_done").unwrap_or(false),
+        pomodoro_count: parse_field(&fields, "pomodoro_count").unwrap_or(0),
+        tags: Vec::new(),
+        due: None,
+        scheduled: None,
+        reminders: Vec::new(),
+        recurrence: None,
+        reset_checkboxes: false,
+        title: fields
+            .get("title")
+            .map(|v| v.to_string())
+            .unwrap_or_default(),
+        created_at: parse_field(&fields, "created_at").unwrap_or(0),
+        updated_at: parse_field(&fields, "updated_at").unwrap_or(0),
+        revision: 0,
+        children: Vec::new(),
+        extra: Mapping::new(),
+    })
+}
+
+/// Broken YAML in a current file is an error rather than a legacy read, so
+/// the file gets reported instead of losing its other keys on the next save.
+fn parse_frontmatter(metadata: &str) -> AppResult<Frontmatter> {
+    serde_yaml::from_str(metadata).or_else(|error| {
+        if is_legacy_frontmatter(metadata) {
+            parse_legacy_frontmatter(metadata)
+        } else {
+            Err(AppError::from(error))
+        }
+    })
+}
+
+fn build_note_content(note: &Note) -> AppResult<String> {
+    Ok(format!(
+        "---\n{}---\n\n{}",
+        build_frontmatter(note)?,
+        note.content
+    ))
+}
+
+fn is_delimiter_line(line: &str) -> bool {
+    line.trim_end_matches(['\n', '\r']) == "---"
+}
+
+fn split_metadata_and_content(content: &str) -> AppResult<(&str, &str)> {
+    let content = content.trim_start_matches('\u{feff}');
+    let after_open = content
+        .strip_prefix("---\n")
+        .or_else(|| content.strip_prefix("---\r\n"))
+        .ok_or_else(|| AppError::parse("Invalid note format"))?;
+
+    // The frontmatter ends at the first line that is exactly `---`
+    let mut offset = 0;
+    for line in after_open.split_inclusive('\n') {
+        if is_delimiter_line(line) {
+            let rest = &after_open[offset + line.len()..];
+            return Ok((&after_open[..offset], rest));
+        }
+        offset += line.len();
     }
+    Err(AppError::parse("Invalid note format"))
+}
 
-    parse_recursive(children_str, parent_id)
+/// Indent of a line that opens an old inline child block: `---` followed by
+/// an `id:` line, both at the same indent.
+fn inline_block_indent(lines: &[&str], i: usize) -> Option<usize> {
+    let line = lines[i].trim_end_matches(['\n', '\r']);
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    let prefix = &line[..indent];
+    let opens_id = lines.get(i + 1).is_some_and(|next| {
+        next.strip_prefix(prefix)
+            .is_some_and(|next| next.starts_with("id:"))
+    });
+    (&line[indent..] == "---" && opens_id).then_some(indent)
 }
 
-fn compose_note(note: Note, content: String, children: Vec<Note>) -> Note {
-    Note {
-        content,
-        children,
-        ..note
+fn attach_top_block(stack: &mut Vec<(usize, Note)>, roots: &mut Vec<Note>, parent_id: &str) {
+    if let Some((_, block)) = stack.pop() {
+        match stack.last_mut() {
+            Some((_, parent)) => parent.children.push(Note {
+                parent_id: Some(parent.id.clone()),
+                ..block
+            }),
+            None => roots.push(Note {
+                parent_id: Some(parent_id.to_string()),
+                ..block
+            }),
+        }
+    }
+}
+
+/// Files from before each note had its own file kept the children after the
+/// body, as legacy frontmatter blocks indented two spaces per level with
+/// their own body below. Splits those off the body and rebuilds the tree.
+fn split_inline_children(body: &str, parent_id: &str) -> (String, Vec<Note>) {
+    let lines: Vec<&str> = body.split_inclusive('\n').collect();
+    let Some(first) = (0..lines.len()).find(|&i| inline_block_indent(&lines, i) == Some(0)) else {
+        return (body.to_string(), Vec::new());
+    };
+
+    let mut stack: Vec<(usize, Note)> = Vec::new();
+    let mut roots = Vec::new();
+    let mut i = first;
+    while let Some(indent) = (i < lines.len())
+        .then(|| inline_block_indent(&lines, i))
+        .flatten()
+    {
+        let prefix = " ".repeat(indent);
+        let unindent = |line: &str| {
+            line.strip_prefix(prefix.as_str())
+                .unwrap_or(line)
+                .to_string()
+        };
+
+        let closing = (i + 1..lines.len())
+            .find(|&j| lines[j].trim_end_matches(['\n', '\r']) == format!("{}---", prefix))
+            .unwrap_or(lines.len());
+        let metadata: String = lines[i + 1..closing]
+            .iter()
+            .map(|line| unindent(line))
+            .collect();
+        let body_start = (closing + 1).min(lines.len());
+        let next = (body_start..lines.len())
+            .find(|&k| inline_block_indent(&lines, k).is_some())
+            .unwrap_or(lines.len());
+        let content: String = lines[body_start..next]
+            .iter()
+            .map(|line| unindent(line))
+            .collect();
+        i = next;
+
+        // Blocks that don't parse stay lost, as they were for the old reader
+        let Ok(frontmatter) = parse_legacy_frontmatter(&metadata) else {
+            continue;
+        };
+        while stack.last().is_some_and(|(top, _)| *top >= indent) {
+            attach_top_block(&mut stack, &mut roots, parent_id);
+        }
+        let (note, _) = from_frontmatter(frontmatter);
+        // Blocks were joined with a newline
+        let content = content.strip_suffix('\n').unwrap_or(&content).to_string();
+        stack.push((indent, Note { content, ..note }));
+    }
+    while !stack.is_empty() {
+        attach_top_block(&mut stack, &mut roots, parent_id);
     }
+
+    let own = lines[..first].concat();
+    let own = own.strip_suffix("\n\n").unwrap_or(&own).to_string();
+    (own, roots)
 }
 
-pub fn parse_note_file(content: &str, parent_id: Option<String>) -> Result<Note, String> {
+/// Old files may come with their children inline; those are returned in
+/// `children` and need `migrate_inline_children` to get files of their own.
+fn parse_note_parts(content: &str, parent_id: Option<String>) -> AppResult<(Note, Vec<String>)> {
     let (metadata, rest) = split_metadata_and_content(content)?;
-    let note = extract_fields(metadata)?;
-    let note_with_parent = Note {
+    let legacy = is_legacy_frontmatter(metadata);
+    let (note, child_order) = from_frontmatter(parse_frontmatter(metadata)?);
+
+    // Drop the blank line `build_note_content` puts after the frontmatter
+    let body = rest.strip_prefix('\n').unwrap_or(rest);
+    let (body, children) = if legacy {
+        split_inline_children(body, &note.id)
+    } else {
+        (body.to_string(), Vec::new())
+    };
+    let child_order = if children.is_empty() {
+        child_order
+    } else {
+        children.iter().map(|child| child.id.clone()).collect()
+    };
+    let parsed = Note {
         parent_id: parent_id.or(note.parent_id),
+        content: body,
+        children,
         ..note
     };
+    Ok((parsed, child_order))
+}
 
-    let (note_content, children_section) = find_nested_section(rest);
-    let children = children_section
-        .as_ref()
-        .and_then(|cs| parse_nested_notes(cs, &note_with_parent.id).ok())
-        .unwrap_or_default();
+fn attach_children(
+    note: Note,
+    child_orders: &HashMap<String, Vec<String>>,
+    by_parent: &mut HashMap<String, Vec<Note>>,
+) -> Note {
+    let order = child_orders.get(&note.id).cloned().unwrap_or_default();
+    let position = |id: &str| order.iter().position(|o| o == id).unwrap_or(usize::MAX);
+
+    // Listed children first, anything the parent doesn't know about after
+    let mut children = by_parent.remove(&note.id).unwrap_or_default();
+    children.sort_by(|a, b| {
+        (position(&a.id), a.created_at, &a.id).cmp(&(position(&b.id), b.created_at, &b.id))
+    });
+
+    let children = children
+        .into_iter()
+        .map(|child| attach_children(child, child_orders, by_parent))
+        .collect();
+    Note { children, ..note }
+}
+
+/// Rebuilds the note tree from flat files. Notes whose parent is missing,
+/// or that sit in a `parent_id` cycle, become roots instead of vanishing.
+pub(crate) fn assemble_tree(flat: Vec<(Note, Vec<String>)>) -> Vec<Note> {
+    let ids: HashSet<String> = flat.iter().map(|(note, _)| note.id.clone()).collect();
+    let child_orders: HashMap<String, Vec<String>> = flat
+        .iter()
+        .map(|(note, order)| (note.id.clone(), order.clone()))
+        .collect();
+
+    let mut roots = Vec::new();
+    let mut by_parent: HashMap<String, Vec<Note>> = HashMap::new();
+    for (note, _) in flat {
+        match note.parent_id.clone().filter(|parent| ids.contains(parent)) {
+            Some(parent) => by_parent.entry(parent).or_default().push(note),
+            None => roots.push(Note {
+                parent_id: None,
+                ..note
+            }),
+        }
+    }
+    roots.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+
+    let mut tree: Vec<Note> = roots
+        .into_iter()
+        .map(|root| attach_children(root, &child_orders, &mut by_parent))
+        .collect();
+
+    while let Some(orphan) = by_parent.values_mut().find_map(|children| children.pop()) {
+        let root = Note {
+            parent_id: None,
+            ..orphan
+        };
+        tree.push(attach_children(root, &child_orders, &mut by_parent));
+        by_parent.retain(|_, children| !children.is_empty());
+    }
+    tree
+}
 
-    Ok(compose_note(note_with_parent, note_content, children))
+/// Inverse of `assemble_tree`: each note without its children, paired with
+/// the ids of those children as its file lists them.
+pub(crate) fn flatten_tree(notes: &[Note]) -> Vec<(Note, Vec<String>)> {
+    notes
+        .iter()
+        .flat_map(|note| {
+            let order = note.children.iter().map(|child| child.id.clone()).collect();
+            let own = Note {
+                children: Vec::new(),
+                ..note.clone()
+            };
+            std::iter::once((own, order)).chain(flatten_tree(&note.children))
+        })
+        .collect()
 }
 
 // I/O operations
 
-pub fn save_note(note: &Note) -> Result<(), String> {
-    if note.parent_id.is_some() {
-        return Ok(());
+/// Where in-flight writes for `path` go; hidden and not `.md`, so a
+/// leftover from a crash is never mistaken for a note.
+fn temp_path_for(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.tmp", name))
+}
+
+/// Replaces `path` with `content` so that a crash at any point leaves
+/// either the old file or the new one, never a truncated mix.
+pub(crate) fn write_atomic(path: &Path, content: &str) -> AppResult<()> {
+    let temp_path = temp_path_for(path);
+    let written = File::create(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(content.as_bytes())?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp_path, path));
+
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp_path);
+        return Err(AppError::from(e).with_context(path.display()));
     }
 
-    let path = get_note_path(&note.id)?;
-    let content = build_note_content(note);
-    fs::write(path, content).map_err(|e| e.to_string())
+    // Persist the rename itself; directories can't be opened on every platform
+    if let Some(dir) = path.parent().and_then(|dir| File::open(dir).ok()) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
 }
 
-fn load_note_files() -> Result<Vec<PathBuf>, String> {
-    get_notes_dir().and_then(|notes_dir| {
-        fs::read_dir(notes_dir)
-            .map_err(|e| e.to_string())
-            .map(|dir_entries| {
-                dir_entries
-                    .filter_map(|entry| entry.ok())
-                    .map(|entry| entry.path())
-                    .filter(|path| path.extension().and_then(|s| s.to_str()) == Some("md"))
-                    .collect()
-            })
-    })
+/// Writes only this note's own file, e.g. after its fields or the order of
+/// its children changed.
+pub fn save_single_note(notes_dir: &Path, note: &Note) -> AppResult<()> {
+    let path = get_note_path(notes_dir, &note.id)?;
+    let content = build_note_content(note)?;
+    write_atomic(&path, &content)
 }
 
-fn read_note_from_path(path: PathBuf) -> Option<Note> {
+/// Writes a note and every descendant to their own files.
+pub fn save_note(notes_dir: &Path, note: &Note) -> AppResult<()> {
+    save_single_note(notes_dir, note)?;
+    note.children
+        .iter()
+        .try_for_each(|child| save_note(notes_dir, child))
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension().and_then(|s| s.to_str()) == Some(extension)
+}
+
+fn list_notes_dir(notes_dir: &Path) -> AppResult<Vec<PathBuf>> {
+    create_notes_dir(notes_dir)?;
+    fs::read_dir(notes_dir)
+        .context(notes_dir.display())
+        .map(|dir_entries| {
+            dir_entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .collect()
+        })
+}
+
+/// Whether `path` is named the way `temp_path_for` names a note's temp file.
+/// A vault can be any folder, so other `.tmp` files aren't ours to touch.
+fn is_note_temp_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_prefix('.'))
+        .and_then(|name| name.strip_suffix(".tmp"))
+        .is_some_and(|name| name.len() > ".md".len() && name.ends_with(".md"))
+}
+
+/// A temp file only survives a write that never reached its rename, and the
+/// note it belonged to is still intact, so it is safe to discard.
+fn remove_stale_temp_files(paths: &[PathBuf]) -> Vec<ScanFailure> {
+    paths
+        .iter()
+        .filter(|path| is_note_temp_file(path))
+        .filter_map(|path| {
+            fs::remove_file(path)
+                .context(path.display())
+                .err()
+                .map(|e| ScanFailure::new(path, e))
+        })
+        .collect()
+}
+
+/// Whether `path` is a note file rather than a temp file or editor leftover.
+pub(crate) fn is_note_file(path: &Path) -> bool {
+    let hidden = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+    has_extension(path, "md") && !hidden
+}
+
+/// Reads one note file; its children are only listed by id, except in old
+/// files that still hold them inline.
+pub fn parse_note_file(path: &Path) -> AppResult<(Note, Vec<String>)> {
     fs::read_to_string(path)
-        .ok()
-        .and_then(|content| parse_note_file(&content, None).ok())
+        .map_err(AppError::from)
+        .and_then(|content| parse_note_parts(&content, None))
+        .map_err(|error| error.with_context(path.display()))
 }
 
-pub fn load_all_notes() -> Result<Vec<Note>, String> {
-    load_note_files().map(|paths| {
-        paths
-            .into_par_iter()
-            .filter_map(read_note_from_path)
-            .collect()
+fn read_note_from_path(path: PathBuf) -> Result<(PathBuf, Note, Vec<String>), UnreadableNote> {
+    parse_note_file(&path)
+        .map(|(note, child_order)| (path.clone(), note, child_order))
+        .map_err(|error| UnreadableNote {
+            error,
+            path: path.display().to_string(),
+        })
+}
+
+fn migrate_note(notes_dir: &Path, path: &PathBuf, note: Note, id: String) -> AppResult<Note> {
+    let migrated = with_id(note, id);
+    save_single_note(notes_dir, &migrated)?;
+    fs::remove_file(path).context(path.display())?;
+    Ok(migrated)
+}
+
+/// Gives inline children that would clash or that carry ids no longer
+/// accepted a fresh one, recording every id it hands out in `taken`.
+fn claim_child_ids(note: Note, taken: &mut HashMap<String, Option<String>>) -> Note {
+    let children = note
+        .children
+        .into_iter()
+        .map(|child| {
+            // A file already filed under this parent is one a crashed
+            // migration wrote before it could rewrite the parent
+            let left_behind = taken.get(&child.id) == Some(&Some(note.id.clone()));
+            let child = if is_valid_id(&child.id) && (left_behind || !taken.contains_key(&child.id))
+            {
+                child
+            } else {
+                let id = unique_id(sanitize_id(&child.id), |candidate| {
+                    taken.contains_key(candidate)
+                });
+                with_id(child, id)
+            };
+            taken.insert(child.id.clone(), None);
+            claim_child_ids(child, taken)
+        })
+        .collect();
+    Note { children, ..note }
+}
+
+/// Children first, so a crash leaves the parent still holding them inline
+/// and the next scan writes the same files again.
+fn save_children_first(notes_dir: &Path, note: &Note) -> AppResult<()> {
+    note.children
+        .iter()
+        .try_for_each(|child| save_children_first(notes_dir, child))?;
+    save_single_note(notes_dir, note)
+}
+
+/// Moves children stored inline by old builds out to their own files and
+/// rewrites the parent to list them by id. Notes without any pass through.
+/// `taken` maps every id in use to the parent its file names.
+pub(crate) fn migrate_inline_children(
+    notes_dir: &Path,
+    loaded: Vec<(PathBuf, Note, Vec<String>)>,
+    taken: &mut HashMap<String, Option<String>>,
+    failures: &mut Vec<ScanFailure>,
+) -> Vec<(PathBuf, Note, Vec<String>)> {
+    let (inline, plain): (Vec<_>, Vec<_>) = loaded
+        .into_iter()
+        .partition(|(_, note, _)| !note.children.is_empty());
+
+    let migrated: Vec<_> = inline
+        .into_iter()
+        .flat_map(|(path, note, _)| {
+            // Renamed here rather than by `migrate_invalid_ids`, which would
+            // write the parent out without its children
+            let note = if is_valid_id(&note.id) {
+                note
+            } else {
+                let id = unique_id(sanitize_id(&note.id), |candidate| {
+                    taken.contains_key(candidate)
+                });
+                taken.insert(id.clone(), note.parent_id.clone());
+                with_id(note, id)
+            };
+            let note = claim_child_ids(note, taken);
+            let moved = save_children_first(notes_dir, &note).and_then(|_| {
+                let new_path = get_note_path(notes_dir, &note.id)?;
+                if new_path != path {
+                    fs::remove_file(&path).context(path.display())?;
+                }
+                Ok(new_path)
+            });
+            let parent_path = moved.unwrap_or_else(|e| {
+                failures.push(ScanFailure::new(&path, e));
+                path
+            });
+
+            flatten_tree(std::slice::from_ref(&note))
+                .into_iter()
+                .map(|(flat, order)| {
+                    let flat_path = if flat.id == note.id {
+                        parent_path.clone()
+                    } else {
+                        notes_dir.join(format!("{}.md", flat.id))
+                    };
+                    (flat_path, flat, order)
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    // Child files left by a crashed migration were taken back above
+    let moved: HashSet<String> = migrated
+        .iter()
+        .filter(|(_, note, _)| note.parent_id.is_some())
+        .map(|(_, note, _)| note.id.clone())
+        .collect();
+    plain
+        .into_iter()
+        .filter(|(_, note, _)| !moved.contains(&note.id))
+        .chain(migrated)
+        .collect()
+}
+
+/// Older builds derived ids straight from the title, so some files carry ids
+/// that are no longer accepted. Those get a sanitized id and a new file.
+fn migrate_invalid_ids(
+    notes_dir: &Path,
+    loaded: Vec<(PathBuf, Note, Vec<String>)>,
+    failures: &mut Vec<ScanFailure>,
+) -> Vec<(Note, Vec<String>)> {
+    let mut taken: HashSet<String> = loaded
+        .iter()
+        .map(|(_, note, _)| note.id.clone())
+        .filter(|id| is_valid_id(id))
+        .collect();
+
+    loaded
+        .into_iter()
+        .map(|(path, note, child_order)| {
+            if is_valid_id(&note.id) {
+                return (note, child_order);
+            }
+
+            let id = unique_id(sanitize_id(&note.id), |candidate| taken.contains(candidate));
+            taken.insert(id.clone());
+            let migrated = migrate_note(notes_dir, &path, note.clone(), id).unwrap_or_else(|e| {
+                failures.push(ScanFailure::new(&path, e));
+                note
+            });
+            (migrated, child_order)
+        })
+        .collect()
+}
+
+/// Scans the notes directory. Files that can't be read or parsed are left
+/// on disk untouched and reported, rather than silently left out, and so are
+/// migrations that couldn't finish.
+pub fn load_all_notes(notes_dir: &Path) -> AppResult<NotesScan> {
+    let paths = list_notes_dir(notes_dir)?;
+    let mut failures = remove_stale_temp_files(&paths);
+
+    let (loaded, unreadable): (Vec<_>, Vec<_>) = paths
+        .into_par_iter()
+        .filter(|path| is_note_file(path))
+        .map(read_note_from_path)
+        .partition_map(|result| match result {
+            Ok(loaded) => Either::Left(loaded),
+            Err(unreadable) => Either::Right(unreadable),
+        });
+
+    let mut taken: HashMap<String, Option<String>> = loaded
+        .iter()
+        .map(|(_, note, _)| (note.id.clone(), note.parent_id.clone()))
+        .collect();
+    let loaded = migrate_inline_children(notes_dir, loaded, &mut taken, &mut failures);
+    let loaded = migrate_invalid_ids(notes_dir, loaded, &mut failures);
+
+    Ok(NotesScan {
+        notes: assemble_tree(loaded),
+        unreadable,
+        failures,
     })
 }
 
-pub fn delete_note(id: &str) -> Result<(), String> {
-    let path = get_note_path(id)?;
+pub fn delete_note(notes_dir: &Path, id: &str) -> AppResult<()> {
+    let path = get_note_path(notes_dir, id)?;
     if path.exists() {
-        fs::remove_file(path).map_err(|e| e.to_string())?;
+        fs::remove_file(&path).context(path.display())?;
     }
     Ok(())
 }
+
+/// Saved queries live next to the notes so they travel with the vault. The
+/// watcher and the scan only look at `.md` files, so this one is left alone.
+fn get_queries_path(notes_dir: &Path) -> PathBuf {
+    notes_dir.join("queries.json")
+}
+
+pub fn load_saved_queries(notes_dir: &Path) -> AppResult<Vec<SavedQuery>> {
+    let path = get_queries_path(notes_dir);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    fs::read_to_string(&path)
+        .context(path.display())
+        .and_then(|content| serde_json::from_str(&content).context(path.display()))
+}
+
+pub fn save_saved_queries(notes_dir: &Path, queries: &[SavedQuery]) -> AppResult<()> {
+    let path = get_queries_path(notes_dir);
+    let content = serde_json::to_string_pretty(queries)?;
+    write_atomic(&path, &content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(title: &str, content: &str) -> Note {
+        Note {
+            is_task: true,
+            pomodoro_count: 3,
+            ..Note::new(title.to_string(), content.to_string())
+        }
+    }
+
+    fn parse(file: &str) -> Note {
+        parse_note_parts(file, None).unwrap().0
+    }
+
+    fn round_trip(note: &Note) -> Note {
+        let file = build_note_content(note).unwrap();
+        parse(&file)
+    }
+
+    #[test]
+    fn test_hostile_titles_round_trip() {
+        let titles = [
+            "Meeting: Q3",
+            "line one\nline two",
+            "---",
+            "before\n---\nafter",
+            "id: spoofed",
+            "# not a comment",
+            "'single' and \"double\" quotes",
+            "  padded  ",
+            "yes",
+            "42",
+            "[a, b] {c: d}",
+            "tomat 🍅",
+            "",
+        ];
+
+        for title in titles {
+            let parsed = round_trip(&note(title, "body"));
+            assert_eq!(parsed.title, title);
+            assert_eq!(parsed.content, "body");
+            assert!(parsed.is_task);
+            assert_eq!(parsed.pomodoro_count, 3);
+        }
+    }
+
+    #[test]
+    fn test_hostile_content_round_trip() {
+        let contents = [
+            "",
+            "---",
+            "first\n---\nsecond",
+            "---\nid: spoofed\ntitle: evil\n---\n",
+            "trailing newline\n",
+            "\n\nleading blank lines",
+            "- [ ] task\n- [x] done",
+            "windows\r\nline endings",
+        ];
+
+        for content in contents {
+            let original = note("Title", content);
+            let parsed = round_trip(&original);
+            assert_eq!(parsed.content, content);
+            assert_eq!(parsed.id, original.id);
+            assert_eq!(parsed.title, "Title");
+        }
+    }
+
+    #[test]
+    fn test_unknown_keys_preserved() {
+        let file = "---\nid: 1-a\ntitle: Plain\naliases:\n- other\ncolor: red\n---\n\nbody";
+        let parsed = parse(file);
+        let reparsed = round_trip(&parsed);
+
+        assert_eq!(reparsed.extra_frontmatter, parsed.extra_frontmatter);
+        assert_eq!(reparsed.extra_frontmatter.len(), 2);
+        assert!(build_note_content(&reparsed)
+            .unwrap()
+            .contains("color: red"));
+    }
+
+    #[test]
+    fn test_keys_match_exactly() {
+        let file = "---\nid_something: wrong\nid: right\ntitle: T\n---\n\nbody";
+        assert_eq!(parse(file).id, "right");
+    }
+
+    #[test]
+    fn test_legacy_unquoted_frontmatter() {
+        let file = "---\nid: 1700000000-Meeting\nis_task: true\ntitle: Meeting: Q3\n---\n\nbody";
+        let parsed = parse(file);
+        assert_eq!(parsed.title, "Meeting: Q3");
+        assert!(parsed.is_task);
+        assert_eq!(parsed.content, "body");
+    }
+
+    #[test]
+    fn test_broken_yaml_is_not_read_as_legacy() {
+        let scalars = parse("---\nid: 1-a\ntitle: 2024\ntags: [work, 2024]\n---\n\nbody");
+        assert_eq!(scalars.title, "2024");
+        assert_eq!(scalars.tags, ["work", "2024"]);
+
+        // Reading these line by line would drop the due date and the tags
+        for file in [
+            "---\nid: 1-a\ntitle: Meeting: Q3\ndue: 2024-03-01\n---\n\nbody",
+            "---\nid: 1-a\ntitle: Meeting: Q3\ntags:\n- work\n---\n\nbody",
+        ] {
+            let error = parse_note_parts(file, None).err().unwrap();
+            assert_eq!(error.kind, crate::error::ErrorKind::Parse);
+        }
+    }
+
+    fn child(parent: &Note, n: usize, children: Vec<Note>) -> Note {
+        Note {
+            id: format!("{}-{}", parent.id, n),
+            parent_id: Some(parent.id.clone()),
+            title: format!("child {}\n---", n),
+            content: format!("---\nid: spoof-{}\n---\nbody {}\n", n, n),
+            created_at: 100 - n as u64,
+            children,
+            ..parent.clone()
+        }
+    }
+
+    fn build_tree(parent: Note, depth: usize) -> Note {
+        if depth == 0 {
+            return parent;
+        }
+        let children = (0..3)
+            .map(|n| build_tree(child(&parent, n, Vec::new()), depth - 1))
+            .collect();
+        Note { children, ..parent }
+    }
+
+    fn flatten(note: &Note) -> Vec<&Note> {
+        std::iter::once(note)
+            .chain(note.children.iter().flat_map(flatten))
+            .collect()
+    }
+
+    fn assert_same_tree(left: &Note, right: &Note) {
+        assert_eq!(left.id, right.id);
+        assert_eq!(left.parent_id, right.parent_id);
+        assert_eq!(left.title, right.title);
+        assert_eq!(left.content, right.content);
+        assert_eq!(left.children.len(), right.children.len());
+        left.children
+            .iter()
+            .zip(&right.children)
+            .for_each(|(l, r)| assert_same_tree(l, r));
+    }
+
+    fn store_and_reload(roots: &[Note]) -> Vec<Note> {
+        let mut files: Vec<(Note, Vec<String>)> = roots
+            .iter()
+            .flat_map(flatten)
+            .map(|note| parse_note_parts(&build_note_content(note).unwrap(), None).unwrap())
+            .collect();
+        // Directory listing order is arbitrary
+        files.reverse();
+        assemble_tree(files)
+    }
+
+    #[test]
+    fn test_nested_children_round_trip_at_depth() {
+        let root = build_tree(note("Root", "root body"), 5);
+        assert_eq!(flatten(&root).len(), 364);
+
+        let reloaded = store_and_reload(std::slice::from_ref(&root));
+        assert_eq!(reloaded.len(), 1);
+        assert_same_tree(&root, &reloaded[0]);
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("tomatxt-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_without_leftovers() {
+        let dir = scratch_dir("atomic");
+        let path = dir.join("note.md");
+        fs::write(&path, "old").unwrap();
+
+        write_atomic(&path, "new").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert!(!temp_path_for(&path).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_only_note_temp_files_are_removed() {
+        let dir = scratch_dir("temp");
+        let ours = temp_path_for(&dir.join("1-a.md"));
+        let others =
+            ["foo.tmp", ".foo.tmp", ".md.tmp", ".queries.json.tmp"].map(|name| dir.join(name));
+        for path in others.iter().chain([&ours]) {
+            fs::write(path, "x").unwrap();
+        }
+
+        load_all_notes(&dir).unwrap();
+        assert!(!ours.exists());
+        assert!(others.iter().all(|path| path.exists()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_truncated_file_is_reported() {
+        let dir = scratch_dir("truncated");
+        let path = dir.join("1-cut.md");
+        let full = build_note_content(&note("Cut", "body")).unwrap();
+        fs::write(&path, &full[..10]).unwrap();
+
+        let Err(unreadable) = read_note_from_path(path.clone()) else {
+            panic!("truncated note parsed");
+        };
+        assert_eq!(unreadable.error.kind, crate::error::ErrorKind::Parse);
+        assert_eq!(unreadable.path, path.display().to_string());
+        assert!(path.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// How the first release wrote a parent with a child that has its own
+    /// child; bodies of nested blocks are indented along with them.
+    const BASELINE_FILE: &str = "---
+id: 1700000000000-trip
+is_task: true
+is_done: false
+pomodoro_count: 2
+title: Trip: Bali
+created_at: 1700000000
+updated_at: 1700000000
+---
+
+Packing list
+
+---
+id: 1700000000001-tickets
+is_task: true
+is_done: true
+pomodoro_count: 0
+title: Tickets
+created_at: 1700000001
+updated_at: 1700000001
+---
+Book flights
+  ---
+  id: 1700000000002-visa
+  is_task: false
+  is_done: false
+  pomodoro_count: 0
+  title: Visa
+  created_at: 1700000002
+  updated_at: 1700000002
+  ---
+  Check rules
+
+  Passport
+---
+id: 1700000000003-hotel
+is_task: false
+is_done: false
+pomodoro_count: 0
+title: Hotel
+created_at: 1700000003
+updated_at: 1700000003
+---";
+
+    #[test]
+    fn test_baseline_inline_children_are_parsed() {
+        let (parent, order) = parse_note_parts(BASELINE_FILE, None).unwrap();
+        assert_eq!(parent.title, "Trip: Bali");
+        assert_eq!(parent.content, "Packing list");
+        assert_eq!(order, ["1700000000001-tickets", "1700000000003-hotel"]);
+
+        let tickets = &parent.children[0];
+        assert!(tickets.is_done);
+        assert_eq!(tickets.content, "Book flights");
+        assert_eq!(tickets.parent_id.as_deref(), Some("1700000000000-trip"));
+        assert_eq!(tickets.children[0].title, "Visa");
+        assert_eq!(tickets.children[0].content, "Check rules\n\nPassport");
+        assert_eq!(
+            tickets.children[0].parent_id.as_deref(),
+            Some("1700000000001-tickets")
+        );
+        assert_eq!(parent.children[1].content, "");
+        assert!(parent.children[1].children.is_empty());
+    }
+
+    #[test]
+    fn test_baseline_inline_children_get_own_files() {
+        let dir = scratch_dir("inline");
+        let path = dir.join("1700000000000-trip.md");
+        fs::write(&path, BASELINE_FILE).unwrap();
+
+        let scan = load_all_notes(&dir).unwrap();
+        assert_eq!(scan.notes.len(), 1);
+        let trip = &scan.notes[0];
+        assert_eq!(trip.content, "Packing list");
+        assert_eq!(
+            trip.children[0].children[0].content,
+            "Check rules\n\nPassport"
+        );
+
+        let rewritten = fs::read_to_string(&path).unwrap();
+        assert!(!rewritten.contains("Book flights"));
+        assert!(dir.join("1700000000002-visa.md").exists());
+
+        let reloaded = load_all_notes(&dir).unwrap();
+        assert_same_tree(trip, &reloaded.notes[0]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_crashed_inline_migration_is_not_duplicated() {
+        let dir = scratch_dir("inline-crash");
+        fs::write(dir.join("1700000000000-trip.md"), BASELINE_FILE).unwrap();
+
+        // The child files made it to disk but the parent was never rewritten
+        let (trip, _) = parse_note_parts(BASELINE_FILE, None).unwrap();
+        for child in &trip.children {
+            save_children_first(&dir, child).unwrap();
+        }
+
+        let scan = load_all_notes(&dir).unwrap();
+        assert_eq!(scan.notes.len(), 1);
+        let ids: Vec<_> = flatten(&scan.notes[0])
+            .iter()
+            .map(|note| note.id.clone())
+            .collect();
+        assert_eq!(
+            ids.len(),
+            4,
+            "expected trip, tickets, visa and hotel once each: {:?}",
+            ids
+        );
+        assert!(ids.contains(&"1700000000002-visa".to_string()));
+        assert_eq!(list_notes_dir(&dir).unwrap().len(), 4);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_orphans_and_cycles_are_kept() {
+        let a = Note {
+            id: "a".to_string(),
+            parent_id: Some("b".to_string()),
+            ..note("A", "")
+        };
+        let b = Note {
+            id: "b".to_string(),
+            parent_id: Some("a".to_string()),
+            ..note("B", "")
+        };
+        let orphan = Note {
+            id: "orphan".to_string(),
+            parent_id: Some("missing".to_string()),
+            ..note("Orphan", "")
+        };
+
+        let reloaded = store_and_reload(&[a, b, orphan]);
+        let total: usize = reloaded.iter().map(|root| flatten(root).len()).sum();
+        assert_eq!(total, 3);
+        assert!(reloaded.iter().all(|root| root.parent_id.is_none()));
+    }
+}
@@ -0,0 +1,225 @@
+use super::model::Note;
+use super::storage::Scalar;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TagCount {
+    pub name: String,
+    pub count: u32,
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '_' || c == '/'
+}
+
+/// Tag without its `#`, lowercased so `#Work` and `work` are the same tag.
+/// All-digit names like `#1` are issue references, not tags.
+pub fn normalize(tag: &str) -> Option<String> {
+    let name = tag.trim().trim_start_matches('#').trim_end_matches('/');
+    let valid = !name.is_empty()
+        && name.chars().all(is_tag_char)
+        && !name.chars().all(|c| c.is_ascii_digit());
+    valid.then(|| name.to_lowercase())
+}
+
+/// Frontmatter may hold `tags: [a, b]` or, as other tools write it,
+/// `tags: a b` / `tags: a, b`.
+pub fn deserialize_tags<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Tags {
+        List(Vec<Scalar>),
+        Text(String),
+    }
+
+    Ok(match Option::<Tags>::deserialize(deserializer)? {
+        Some(Tags::List(tags)) => tags.into_iter().map(Scalar::into_text).collect(),
+        Some(Tags::Text(text)) => text
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_string)
+            .collect(),
+        None => Vec::new(),
+    })
+}
+
+/// Byte ranges of inline `#tag` names in `content`, `#` excluded. Code
+/// fences and headings (`# Title`) don't count.
+fn inline_tag_ranges(content: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut in_fence = false;
+    let mut offset = 0;
+
+    for line in content.split_inclusive('\n') {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+        } else if !in_fence {
+            let mut previous = None;
+            for (i, c) in line.char_indices() {
+                let at_boundary = previous.is_none_or(|p: char| p.is_whitespace() || p == '(');
+                if c == '#' && at_boundary {
+                    let name = &line[i + 1..];
+                    let len = name.find(|c| !is_tag_char(c)).unwrap_or(name.len());
+                    let name = name[..len].trim_end_matches('/');
+                    if normalize(name).is_some() {
+                        let start = offset + i + 1;
+                        ranges.push((start, start + name.len()));
+                    }
+                }
+                previous = Some(c);
+            }
+        }
+        offset += line.len();
+    }
+    ranges
+}
+
+pub fn inline_tags(content: &str) -> Vec<String> {
+    inline_tag_ranges(content)
+        .into_iter()
+        .filter_map(|(start, end)| normalize(&content[start..end]))
+        .collect()
+}
+
+/// Frontmatter tags and inline hashtags together, sorted and deduplicated.
+pub fn note_tags(note: &Note) -> Vec<String> {
+    note.tags
+        .iter()
+        .filter_map(|tag| normalize(tag))
+        .chain(inline_tags(&note.content))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+pub fn has_tag(note: &Note, tag: &str) -> bool {
+    note_tags(note).iter().any(|t| t == tag)
+}
+
+/// Every tag in the tree, children included, with how many notes carry it.
+pub fn count_tags(notes: &[Note]) -> Vec<TagCount> {
+    fn collect(notes: &[Note], counts: &mut BTreeMap<String, u32>) {
+        for note in notes {
+            for tag in note_tags(note) {
+                *counts.entry(tag).or_default() += 1;
+            }
+            collect(&note.children, counts);
+        }
+    }
+
+    let mut counts = BTreeMap::new();
+    collect(notes, &mut counts);
+    counts
+        .into_iter()
+        .map(|(name, count)| TagCount { name, count })
+        .collect()
+}
+
+/// `tag` renamed to `to` if it is `from` or nested under it (`from/...`).
+fn renamed(tag: &str, from: &str, to: &str) -> Option<String> {
+    let normalized = normalize(tag)?;
+    if normalized == from {
+        Some(to.to_string())
+    } else {
+        normalized
+            .strip_prefix(from)
+            .filter(|rest| rest.starts_with('/'))
+            .map(|rest| format!("{}{}", to, rest))
+    }
+}
+
+/// `note` with tag `from` renamed to `to` in both the frontmatter and the
+/// inline hashtags, or `None` if it doesn't carry the tag. Both names must
+/// already be normalized.
+pub fn rename_in_note(note: &Note, from: &str, to: &str) -> Option<Note> {
+    let mut changed = false;
+
+    let mut tags = Vec::new();
+    for tag in &note.tags {
+        let tag = match renamed(tag, from, to) {
+            Some(new) => {
+                changed = true;
+                new
+            }
+            None => tag.clone(),
+        };
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+
+    let mut content = String::with_capacity(note.content.len());
+    let mut last = 0;
+    for (start, end) in inline_tag_ranges(&note.content) {
+        if let Some(new) = renamed(&note.content[start..end], from, to) {
+            content.push_str(&note.content[last..start]);
+            content.push_str(&new);
+            last = end;
+            changed = true;
+        }
+    }
+    content.push_str(&note.content[last..]);
+
+    changed.then(|| Note {
+        tags,
+        content,
+        ..note.clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(tags: &[&str], content: &str) -> Note {
+        Note {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..Note::new("Tagged".to_string(), content.to_string())
+        }
+    }
+
+    #[test]
+    fn test_inline_tags() {
+        let content = "# Heading\nPlan #Work and #home/garden, not issue #12 or a#b.\n\
+                       ```\n#notatag\n```\n(#errand) #café";
+        assert_eq!(
+            inline_tags(content),
+            ["work", "home/garden", "errand", "café"]
+        );
+    }
+
+    #[test]
+    fn test_note_tags_merge_frontmatter_and_inline() {
+        let note = note(&["Work", "#reading"], "finish #work today #later");
+        assert_eq!(note_tags(&note), ["later", "reading", "work"]);
+    }
+
+    #[test]
+    fn test_rename_in_note() {
+        let note = note(
+            &["work", "admin"],
+            "#Work plan, #work/meeting and #workshop",
+        );
+        let renamed = rename_in_note(&note, "work", "job").unwrap();
+
+        assert_eq!(renamed.tags, ["job", "admin"]);
+        assert_eq!(renamed.content, "#job plan, #job/meeting and #workshop");
+        assert!(rename_in_note(&renamed, "work", "job").is_none());
+    }
+
+    #[test]
+    fn test_frontmatter_tags_as_text() {
+        #[derive(Deserialize)]
+        struct Doc {
+            #[serde(deserialize_with = "deserialize_tags")]
+            tags: Vec<String>,
+        }
+
+        let doc: Doc = serde_yaml::from_str("tags: work, home errands").unwrap();
+        assert_eq!(doc.tags, ["work", "home", "errands"]);
+    }
+}
@@ -0,0 +1,212 @@
+use super::model::Note;
+use super::storage::{ScanFailure, UnreadableNote};
+use super::{storage, NotesState, ERROR_EVENT};
+use crate::error::{AppError, AppResult};
+use notify::{Event, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+use tauri::{AppHandle, Emitter};
+
+pub const CHANGED_EVENT: &str = "notes://changed";
+
+/// Notes whose files changed on disk outside the app, and the changed files
+/// that couldn't be taken in.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct NotesChanged {
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+    pub unreadable: Vec<UnreadableNote>,
+    pub failures: Vec<ScanFailure>,
+}
+
+impl NotesChanged {
+    fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.removed.is_empty()
+    }
+
+    fn is_worth_reporting(&self) -> bool {
+        !self.is_empty() || !self.unreadable.is_empty() || !self.failures.is_empty()
+    }
+}
+
+fn id_from_path(path: &Path) -> Option<String> {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+}
+
+fn is_same_note(known: &Note, parsed: &Note) -> bool {
+    let known = Note {
+        content_without_checkboxes: None,
+        revision: parsed.revision,
+        ..known.clone()
+    };
+    &known == parsed
+}
+
+/// Re-reads the given files into `flat`. Our own saves parse back to exactly
+/// what's in memory, so they are skipped rather than reported.
+fn apply_paths(flat: &mut Vec<(Note, Vec<String>)>, paths: &[PathBuf]) -> NotesChanged {
+    let mut changes = NotesChanged::default();
+
+    for path in paths.iter().filter(|path| storage::is_note_file(path)) {
+        // Editors often delete and recreate on save, so trust the disk now
+        // rather than the event kind
+        if !path.exists() {
+            if let Some(id) = id_from_path(path) {
+                let before = flat.len();
+                flat.retain(|(note, _)| note.id != id);
+                if flat.len() != before {
+                    changes.removed.push(id);
+                }
+            }
+            continue;
+        }
+
+        let (note, order) = match storage::parse_note_file(path) {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                // Possibly caught mid-write, in which case the next event
+                // brings the rest; the file is left alone either way
+                changes.unreadable.push(UnreadableNote {
+                    path: path.display().to_string(),
+                    error,
+                });
+                continue;
+            }
+        };
+
+        // An old-format file copied in may hold its children inline
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let mut taken: HashMap<String, Option<String>> = flat
+            .iter()
+            .map(|(note, _)| (note.id.clone(), note.parent_id.clone()))
+            .collect();
+        let parsed = vec![(path.clone(), note, order)];
+        let migrated =
+            storage::migrate_inline_children(dir, parsed, &mut taken, &mut changes.failures);
+        for (_, note, order) in migrated {
+            match flat.iter_mut().find(|(known, _)| known.id == note.id) {
+                Some(entry) if is_same_note(&entry.0, &note) && entry.1 == order => continue,
+                Some(entry) => {
+                    // Outside edits rarely bump the revision, so do it here to
+                    // make clients holding the old version hit a conflict
+                    let revision = entry.0.revision.max(note.revision) + 1;
+                    *entry = (
+                        Note {
+                            revision,
+                            ..note.clone()
+                        },
+                        order,
+                    );
+                }
+                None => flat.push((note.clone(), order)),
+            }
+            changes.changed.push(note.id);
+        }
+    }
+    changes
+}
+
+fn reload_paths(notes: &NotesState, paths: &[PathBuf]) -> AppResult<NotesChanged> {
+    let mut guard = notes.notes.lock()?;
+    let mut flat = storage::flatten_tree(&guard);
+    let changes = apply_paths(&mut flat, paths);
+    if !changes.is_empty() {
+        let mut index = notes.index.lock()?;
+        changes.removed.iter().for_each(|id| index.remove(id));
+        flat.iter()
+            .filter(|(note, _)| changes.changed.contains(&note.id))
+            .for_each(|(note, _)| index.upsert(note));
+        *guard = storage::assemble_tree(flat);
+    }
+    Ok(changes)
+}
+
+fn handle_event(app: &AppHandle, target: &Weak<NotesState>, event: notify::Result<Event>) {
+    // The vault was switched away from; its watcher is about to go too
+    let Some(notes) = target.upgrade() else {
+        return;
+    };
+
+    let result = event
+        .map_err(|e| AppError::io(e.to_string()).with_context(notes.dir.display()))
+        .and_then(|event| reload_paths(&notes, &event.paths));
+    match result {
+        Ok(changes) if changes.is_worth_reporting() => {
+            let _ = app.emit(CHANGED_EVENT, changes);
+        }
+        Ok(_) => {}
+        Err(e) => {
+            let _ = app.emit(ERROR_EVENT, e);
+        }
+    }
+}
+
+/// Watches the vault's directory for edits made by other programs and keeps
+/// `notes` in sync. The watcher lives and dies with `notes`.
+pub fn watch(app: AppHandle, notes: &Arc<NotesState>) -> AppResult<()> {
+    let target = Arc::downgrade(notes);
+    let watch_error =
+        |e: notify::Error| AppError::io(e.to_string()).with_context(notes.dir.display());
+
+    let mut watcher = notify::recommended_watcher(move |event| handle_event(&app, &target, event))
+        .map_err(watch_error)?;
+    watcher
+        .watch(&notes.dir, RecursiveMode::NonRecursive)
+        .map_err(watch_error)?;
+
+    *notes.watcher.lock()? = Some(watcher);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_apply_paths_skips_own_writes() {
+        let dir = std::env::temp_dir().join(format!("tomatxt-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let note = Note::new("Watched".to_string(), "body".to_string());
+        let paths = [dir.join(format!("{}.md", note.id))];
+        storage::save_single_note(&dir, &note).unwrap();
+
+        let mut flat = vec![(note.clone(), Vec::new())];
+        let unchanged = apply_paths(&mut flat, &paths);
+        assert!(unchanged.is_empty());
+
+        let edited = Note {
+            content: "edited in vim".to_string(),
+            ..note.clone()
+        };
+        storage::save_single_note(&dir, &edited).unwrap();
+        let changes = apply_paths(&mut flat, &paths);
+        assert_eq!(changes.changed, vec![note.id.clone()]);
+        assert_eq!(flat[0].0.content, "edited in vim");
+        assert_eq!(flat[0].0.revision, note.revision + 1);
+
+        fs::remove_file(&paths[0]).unwrap();
+        let changes = apply_paths(&mut flat, &paths);
+        assert_eq!(changes.removed, vec![note.id]);
+        assert!(flat.is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_apply_paths_reports_unreadable_files() {
+        let dir = std::env::temp_dir().join(format!("tomatxt-watch-bad-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("broken.md");
+        fs::write(&path, "---\ntitle: [unclosed\n---\nbody").unwrap();
+
+        let mut flat = Vec::new();
+        let changes = apply_paths(&mut flat, &[path]);
+        assert!(changes.is_empty());
+        assert_eq!(changes.unreadable.len(), 1);
+        assert!(changes.is_worth_reporting());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
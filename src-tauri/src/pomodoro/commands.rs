@@ -1,209 +1,726 @@
+use super::history::{self, PhaseKind, SessionRecord};
+use crate::error::{AppError, AppResult};
+use crate::notes;
+use crate::settings::{self, model::PomodoroSettings, SettingsState};
+use crate::vaults::VaultsState;
 use serde::{Deserialize, Serialize};
 use std::sync::Mutex;
-use tauri::State;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager, State};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+pub const TICK_EVENT: &str = "pomodoro://tick";
+pub const PHASE_FINISHED_EVENT: &str = "pomodoro://phase-finished";
+
+const ENGINE_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct PomodoroState {
     pub work_duration: u32,
     pub break_duration: u32,
+    pub long_break_duration: u32,
+    pub sessions_before_long_break: u32,
+    /// Work phases finished in the current cycle, reset after a long break.
+    pub completed_sessions: u32,
     pub remaining: u32,
     pub is_break: bool,
+    pub is_long_break: bool,
     pub is_paused: bool,
+    /// Monotonic instant at which the running phase reaches zero.
+    /// `None` while paused; `remaining` is then the source of truth.
+    #[serde(skip)]
+    pub deadline: Option<Instant>,
+    /// Wall-clock start of the current phase, `None` until it first runs.
+    #[serde(skip)]
+    pub phase_started_at: Option<u64>,
+    /// Bumped whenever a new phase replaces the current one; unlike
+    /// `phase_started_at` it tells apart phases started in the same second.
+    #[serde(skip)]
+    pub phase_id: u64,
+    /// Note credited with a pomodoro whenever a work phase completes.
+    pub active_note_id: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PhaseFinished {
+    pub kind: PhaseKind,
+    pub was_break: bool,
+    pub state: PomodoroState,
 }
 
 pub struct TimerState {
     pub timer: Mutex<PomodoroState>,
 }
 
+/// Minutes are validated against `MAX_PHASE_MINUTES`, so saturating only
+/// guards against callers that skipped that.
 pub fn to_seconds(minutes: u32) -> u32 {
-    minutes * 60
+    minutes.saturating_mul(60)
+}
+
+/// Paused work phase built from the persisted settings, used at startup.
+pub fn initial_state(settings: &PomodoroSettings) -> PomodoroState {
+    PomodoroState {
+        work_duration: to_seconds(settings.work_minutes),
+        break_duration: to_seconds(settings.break_minutes),
+        long_break_duration: to_seconds(settings.long_break_minutes),
+        sessions_before_long_break: settings.sessions_before_long_break,
+        completed_sessions: 0,
+        remaining: to_seconds(settings.work_minutes),
+        is_break: false,
+        is_long_break: false,
+        is_paused: true,
+        deadline: None,
+        phase_started_at: None,
+        phase_id: 0,
+        active_note_id: None,
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+fn deadline_from(now: Instant, remaining: u32) -> Option<Instant> {
+    Some(now + Duration::from_secs(remaining.into()))
 }
 
-fn modify_timer_state<F>(state: &State<TimerState>, modifier: F) -> PomodoroState
+fn remaining_until(deadline: Instant, now: Instant) -> u32 {
+    // Round up so a freshly started 25:00 phase reads 25:00, not 24:59
+    deadline
+        .saturating_duration_since(now)
+        .as_millis()
+        .div_ceil(1000) as u32
+}
+
+fn modify_timer_state<F>(state: &State<TimerState>, modifier: F) -> AppResult<PomodoroState>
 where
-    F: FnOnce(&PomodoroState) -> PomodoroState,
+    F: FnOnce(&PomodoroState, Instant) -> PomodoroState,
 {
-    let guard = state.timer.lock().unwrap();
-    let new_state = modifier(&guard);
-    drop(guard);
-    *state.timer.lock().unwrap() = new_state.clone();
-    new_state
+    let (new_state, abandoned) = {
+        let mut guard = state.timer.lock()?;
+        let now = Instant::now();
+        let new_state = modifier(&guard, now);
+        let abandoned = abandoned_record(&guard, &new_state, now);
+        *guard = new_state.clone();
+        (new_state, abandoned)
+    };
+
+    save_record(abandoned);
+    Ok(new_state)
 }
 
-fn start_work_timer(work_duration: u32, break_duration: u32) -> PomodoroState {
+/// Replacing a phase that already ran means it was cut short.
+fn abandoned_record(
+    old: &PomodoroState,
+    new: &PomodoroState,
+    now: Instant,
+) -> Option<SessionRecord> {
+    (old.phase_id != new.phase_id)
+        .then(|| phase_record(&tick(old, now), true))
+        .flatten()
+}
+
+fn start_work_timer(state: &PomodoroState, now: Instant) -> PomodoroState {
     PomodoroState {
-        work_duration,
-        break_duration,
         is_break: false,
-        remaining: work_duration,
+        is_long_break: false,
+        remaining: state.work_duration,
         is_paused: false,
+        deadline: deadline_from(now, state.work_duration),
+        phase_started_at: Some(unix_now()),
+        phase_id: state.phase_id + 1,
+        ..state.clone()
     }
 }
 
-fn start_break_timer(work_duration: u32, break_duration: u32) -> PomodoroState {
+fn start_break_timer(state: &PomodoroState, now: Instant) -> PomodoroState {
     PomodoroState {
-        work_duration,
-        break_duration,
         is_break: true,
-        remaining: break_duration,
+        is_long_break: false,
+        remaining: state.break_duration,
         is_paused: false,
+        deadline: deadline_from(now, state.break_duration),
+        phase_started_at: Some(unix_now()),
+        phase_id: state.phase_id + 1,
+        ..state.clone()
     }
 }
 
-fn tick(state: &PomodoroState) -> PomodoroState {
-    if state.is_paused || state.remaining == 0 {
-        return state.clone();
+fn start_long_break_timer(state: &PomodoroState, now: Instant) -> PomodoroState {
+    PomodoroState {
+        is_break: true,
+        is_long_break: true,
+        remaining: state.long_break_duration,
+        is_paused: false,
+        deadline: deadline_from(now, state.long_break_duration),
+        phase_started_at: Some(unix_now()),
+        phase_id: state.phase_id + 1,
+        ..state.clone()
     }
+}
 
-    PomodoroState {
-        remaining: state.remaining - 1,
-        ..*state
+fn phase_kind(state: &PomodoroState) -> PhaseKind {
+    match (state.is_break, state.is_long_break) {
+        (false, _) => PhaseKind::Work,
+        (true, false) => PhaseKind::Break,
+        (true, true) => PhaseKind::LongBreak,
+    }
+}
+
+fn phase_duration(state: &PomodoroState) -> u32 {
+    match phase_kind(state) {
+        PhaseKind::Work => state.work_duration,
+        PhaseKind::Break => state.break_duration,
+        PhaseKind::LongBreak => state.long_break_duration,
     }
 }
 
-fn pause(state: &PomodoroState) -> PomodoroState {
+fn tick(state: &PomodoroState, now: Instant) -> PomodoroState {
+    match state.deadline {
+        Some(deadline) if !state.is_paused => PomodoroState {
+            remaining: remaining_until(deadline, now),
+            ..state.clone()
+        },
+        _ => state.clone(),
+    }
+}
+
+fn pause(state: &PomodoroState, now: Instant) -> PomodoroState {
     PomodoroState {
         is_paused: true,
-        ..*state
+        deadline: None,
+        ..tick(state, now)
     }
 }
 
-fn resume(state: &PomodoroState) -> PomodoroState {
+fn resume(state: &PomodoroState, now: Instant) -> PomodoroState {
+    if !state.is_paused {
+        return state.clone();
+    }
+
     PomodoroState {
         is_paused: false,
-        ..*state
+        deadline: deadline_from(now, state.remaining),
+        phase_started_at: state.phase_started_at.or_else(|| Some(unix_now())),
+        ..state.clone()
     }
 }
 
 fn reset(state: &PomodoroState) -> PomodoroState {
-    let reset_seconds = if state.is_break {
-        state.break_duration
+    PomodoroState {
+        remaining: phase_duration(state),
+        is_paused: true,
+        deadline: None,
+        phase_started_at: None,
+        phase_id: state.phase_id + 1,
+        ..state.clone()
+    }
+}
+
+fn restart_phase_if(kind: PhaseKind, state: PomodoroState, now: Instant) -> PomodoroState {
+    if phase_kind(&state) != kind {
+        return state;
+    }
+
+    // The restarted phase is a new one, so the time already worked on the
+    // old one gets recorded rather than folded into it
+    let seconds = phase_duration(&state);
+    let (deadline, phase_started_at) = if state.is_paused {
+        (None, None)
+    } else {
+        (deadline_from(now, seconds), Some(unix_now()))
+    };
+    PomodoroState {
+        remaining: seconds,
+        deadline,
+        phase_started_at,
+        phase_id: state.phase_id + 1,
+        ..state
+    }
+}
+
+fn with_work_duration(state: &PomodoroState, seconds: u32, now: Instant) -> PomodoroState {
+    let updated = PomodoroState {
+        work_duration: seconds,
+        ..state.clone()
+    };
+    restart_phase_if(PhaseKind::Work, updated, now)
+}
+
+fn with_break_duration(state: &PomodoroState, seconds: u32, now: Instant) -> PomodoroState {
+    let updated = PomodoroState {
+        break_duration: seconds,
+        ..state.clone()
+    };
+    restart_phase_if(PhaseKind::Break, updated, now)
+}
+
+fn with_long_break_duration(state: &PomodoroState, seconds: u32, now: Instant) -> PomodoroState {
+    let updated = PomodoroState {
+        long_break_duration: seconds,
+        ..state.clone()
+    };
+    restart_phase_if(PhaseKind::LongBreak, updated, now)
+}
+
+/// Only durations that actually changed restart their phase, so saving
+/// unrelated settings doesn't reset a running timer.
+fn with_settings(
+    state: &PomodoroState,
+    settings: &PomodoroSettings,
+    now: Instant,
+) -> PomodoroState {
+    let state = tick(state, now);
+    let work = to_seconds(settings.work_minutes);
+    let short_break = to_seconds(settings.break_minutes);
+    let long_break = to_seconds(settings.long_break_minutes);
+
+    let state = if state.work_duration != work {
+        with_work_duration(&state, work, now)
+    } else {
+        state
+    };
+    let state = if state.break_duration != short_break {
+        with_break_duration(&state, short_break, now)
+    } else {
+        state
+    };
+    let state = if state.long_break_duration != long_break {
+        with_long_break_duration(&state, long_break, now)
     } else {
-        state.work_duration
+        state
     };
 
     PomodoroState {
-        remaining: reset_seconds,
-        is_paused: true,
-        ..*state
+        sessions_before_long_break: settings.sessions_before_long_break,
+        ..state
     }
 }
 
+fn update_pomodoro_settings_with<F>(
+    state: &State<TimerState>,
+    settings_state: &State<SettingsState>,
+    modifier: F,
+) -> AppResult<PomodoroState>
+where
+    F: FnOnce(PomodoroSettings) -> PomodoroSettings,
+{
+    let settings = settings::update_settings(settings_state, |mut settings| {
+        settings.pomodoro = modifier(settings.pomodoro);
+        settings
+    })?;
+
+    modify_timer_state(state, |s, now| with_settings(s, &settings.pomodoro, now))
+}
+
 fn is_finished(state: &PomodoroState) -> bool {
     state.remaining == 0
 }
 
-fn is_break_finished(state: &PomodoroState) -> bool {
-    state.is_break && is_finished(state)
+fn finish_work_session(state: &PomodoroState, start: Instant) -> PomodoroState {
+    let counted = PomodoroState {
+        completed_sessions: state.completed_sessions + 1,
+        ..state.clone()
+    };
+
+    if counted.completed_sessions >= counted.sessions_before_long_break.max(1) {
+        start_long_break_timer(&counted, start)
+    } else {
+        start_break_timer(&counted, start)
+    }
 }
 
-fn is_work_finished(state: &PomodoroState) -> bool {
-    !state.is_break && is_finished(state)
+fn next_state(state: &PomodoroState, now: Instant) -> PomodoroState {
+    if !is_finished(state) {
+        return state.clone();
+    }
+
+    // Chain the next phase off the previous deadline so phases don't drift
+    let start = state.deadline.unwrap_or(now);
+    match phase_kind(state) {
+        PhaseKind::Work => finish_work_session(state, start),
+        PhaseKind::Break => start_work_timer(state, start),
+        PhaseKind::LongBreak => start_work_timer(
+            &PomodoroState {
+                completed_sessions: 0,
+                ..state.clone()
+            },
+            start,
+        ),
+    }
 }
 
-fn next_state(state: &PomodoroState) -> PomodoroState {
-    match (is_work_finished(state), is_break_finished(state)) {
-        (true, _) => start_break_timer(state.work_duration, state.break_duration),
-        (_, true) => start_work_timer(state.work_duration, state.break_duration),
-        _ => state.clone(),
+fn phase_record(state: &PomodoroState, interrupted: bool) -> Option<SessionRecord> {
+    let planned_duration = phase_duration(state);
+    state.phase_started_at.map(|started_at| SessionRecord {
+        kind: phase_kind(state),
+        started_at,
+        ended_at: unix_now(),
+        planned_duration,
+        actual_duration: planned_duration.saturating_sub(state.remaining),
+        interrupted,
+        note_id: state.active_note_id.clone(),
+    })
+}
+
+fn save_record(record: Option<SessionRecord>) {
+    if let Some(Err(e)) = record.map(|record| history::append_record(&record)) {
+        eprintln!("Failed to record session: {}", e);
     }
 }
 
-#[tauri::command(rename_all = "snake_case")]
-pub fn init_timer(work_min: u32, break_min: u32, state: State<TimerState>) -> PomodoroState {
-    let new_state = PomodoroState {
-        work_duration: to_seconds(work_min),
-        break_duration: to_seconds(break_min),
-        remaining: to_seconds(work_min),
-        is_break: false,
-        is_paused: false,
+fn advance(state: &PomodoroState, now: Instant) -> (PomodoroState, Option<PhaseFinished>) {
+    let ticked = tick(state, now);
+    if ticked.is_paused || !is_finished(&ticked) {
+        return (ticked, None);
+    }
+
+    let next = next_state(&ticked, now);
+    let finished = PhaseFinished {
+        kind: phase_kind(&ticked),
+        was_break: ticked.is_break,
+        state: next.clone(),
     };
+    (next, Some(finished))
+}
+
+fn advance_timer(app: &AppHandle, timer: &TimerState) -> AppResult<PomodoroState> {
+    let (new_state, finished, completed) = {
+        let mut guard = timer.timer.lock()?;
+        let now = Instant::now();
+        let (new_state, finished) = advance(&guard, now);
+        let completed = finished
+            .as_ref()
+            .and_then(|_| phase_record(&tick(&guard, now), false));
+        *guard = new_state.clone();
+        (new_state, finished, completed)
+    };
+
+    if let Some(note_id) = completed
+        .as_ref()
+        .filter(|record| record.kind == PhaseKind::Work)
+        .and_then(|record| record.note_id.as_deref())
+    {
+        if let Err(e) = app
+            .state::<VaultsState>()
+            .active()
+            .and_then(|notes| notes::commands::increment_pomodoro_count(note_id, &notes))
+        {
+            eprintln!("Failed to credit pomodoro to note {}: {}", note_id, e);
+        }
+    }
+    save_record(completed);
 
-    *state.timer.lock().unwrap() = new_state.clone();
-    new_state
+    // Nobody listening is fine, the engine keeps counting regardless
+    if let Some(finished) = finished {
+        let _ = app.emit(PHASE_FINISHED_EVENT, finished);
+    }
+    Ok(new_state)
+}
+
+/// Runs the timer on the Rust side and broadcasts every visible change to
+/// all windows, so the frontend only has to render what it receives.
+pub fn spawn_timer_task(app: AppHandle) {
+    thread::spawn(move || {
+        let mut last_emitted: Option<PomodoroState> = None;
+        loop {
+            // A poisoned timer stays poisoned, so stop instead of spinning;
+            // commands keep reporting the error to the frontend
+            let state = match advance_timer(&app, &app.state::<TimerState>()) {
+                Ok(state) => state,
+                Err(e) => {
+                    eprintln!("Timer engine stopped: {}", e);
+                    return;
+                }
+            };
+            if last_emitted.as_ref() != Some(&state) {
+                let _ = app.emit(TICK_EVENT, state.clone());
+                last_emitted = Some(state);
+            }
+            thread::sleep(ENGINE_INTERVAL);
+        }
+    });
 }
 
 #[tauri::command(rename_all = "snake_case")]
-pub fn get_timer_state(state: State<TimerState>) -> PomodoroState {
-    state.timer.lock().unwrap().clone()
+pub fn init_timer(
+    work_min: u32,
+    break_min: u32,
+    state: State<TimerState>,
+) -> AppResult<PomodoroState> {
+    settings::model::validate_duration("Work duration", work_min)?;
+    settings::model::validate_duration("Break duration", break_min)?;
+
+    modify_timer_state(&state, |s, now| {
+        let configured = PomodoroState {
+            work_duration: to_seconds(work_min),
+            break_duration: to_seconds(break_min),
+            ..s.clone()
+        };
+        start_work_timer(&configured, now)
+    })
 }
 
 #[tauri::command(rename_all = "snake_case")]
-pub fn tick_timer(state: State<TimerState>) -> PomodoroState {
-    modify_timer_state(&state, |timer| next_state(&tick(timer)))
+pub fn get_timer_state(state: State<TimerState>) -> AppResult<PomodoroState> {
+    modify_timer_state(&state, tick)
 }
 
+/// Kept for older frontends; the background task already advances the timer.
 #[tauri::command(rename_all = "snake_case")]
-pub fn start_work(state: State<TimerState>) -> PomodoroState {
-    modify_timer_state(&state, |s| {
-        start_work_timer(s.work_duration, s.break_duration)
-    })
+pub fn tick_timer(app: AppHandle, state: State<TimerState>) -> AppResult<PomodoroState> {
+    advance_timer(&app, &state)
 }
 
 #[tauri::command(rename_all = "snake_case")]
-pub fn start_break(state: State<TimerState>) -> PomodoroState {
-    modify_timer_state(&state, |s| {
-        start_break_timer(s.work_duration, s.break_duration)
-    })
+pub fn start_work(state: State<TimerState>) -> AppResult<PomodoroState> {
+    modify_timer_state(&state, start_work_timer)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub fn start_break(state: State<TimerState>) -> AppResult<PomodoroState> {
+    modify_timer_state(&state, start_break_timer)
 }
 
 #[tauri::command(rename_all = "snake_case")]
-pub fn pause_timer(state: State<TimerState>) -> PomodoroState {
+pub fn start_long_break(state: State<TimerState>) -> AppResult<PomodoroState> {
+    modify_timer_state(&state, start_long_break_timer)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub fn pause_timer(state: State<TimerState>) -> AppResult<PomodoroState> {
     modify_timer_state(&state, pause)
 }
 
 #[tauri::command(rename_all = "snake_case")]
-pub fn resume_timer(state: State<TimerState>) -> PomodoroState {
+pub fn resume_timer(state: State<TimerState>) -> AppResult<PomodoroState> {
     modify_timer_state(&state, resume)
 }
 
 #[tauri::command(rename_all = "snake_case")]
-pub fn reset_timer(state: State<TimerState>) -> PomodoroState {
-    modify_timer_state(&state, reset)
+pub fn reset_timer(state: State<TimerState>) -> AppResult<PomodoroState> {
+    modify_timer_state(&state, |s, _| reset(s))
 }
 
 #[tauri::command(rename_all = "snake_case")]
-pub fn update_work_duration(state: State<TimerState>, minutes: u32) -> PomodoroState {
-    let guard = state.timer.lock().unwrap();
-    let new_duration = to_seconds(minutes);
+pub fn update_work_duration(
+    state: State<TimerState>,
+    settings_state: State<SettingsState>,
+    minutes: u32,
+) -> AppResult<PomodoroState> {
+    update_pomodoro_settings_with(&state, &settings_state, |settings| PomodoroSettings {
+        work_minutes: minutes,
+        ..settings
+    })
+}
 
-    let new_state = PomodoroState {
-        work_duration: new_duration,
-        remaining: if !guard.is_break {
-            new_duration
-        } else {
-            guard.remaining
-        },
-        ..guard.clone()
-    };
-    drop(guard);
+#[tauri::command(rename_all = "snake_case")]
+pub fn update_break_duration(
+    state: State<TimerState>,
+    settings_state: State<SettingsState>,
+    minutes: u32,
+) -> AppResult<PomodoroState> {
+    update_pomodoro_settings_with(&state, &settings_state, |settings| PomodoroSettings {
+        break_minutes: minutes,
+        ..settings
+    })
+}
 
-    *state.timer.lock().unwrap() = new_state.clone();
-    new_state
+#[tauri::command(rename_all = "snake_case")]
+pub fn update_long_break_duration(
+    state: State<TimerState>,
+    settings_state: State<SettingsState>,
+    minutes: u32,
+) -> AppResult<PomodoroState> {
+    update_pomodoro_settings_with(&state, &settings_state, |settings| PomodoroSettings {
+        long_break_minutes: minutes,
+        ..settings
+    })
 }
 
 #[tauri::command(rename_all = "snake_case")]
-pub fn update_break_duration(state: State<TimerState>, minutes: u32) -> PomodoroState {
-    let guard = state.timer.lock().unwrap();
-    let new_duration = to_seconds(minutes);
+pub fn update_sessions_before_long_break(
+    state: State<TimerState>,
+    settings_state: State<SettingsState>,
+    sessions: u32,
+) -> AppResult<PomodoroState> {
+    update_pomodoro_settings_with(&state, &settings_state, |settings| PomodoroSettings {
+        sessions_before_long_break: sessions,
+        ..settings
+    })
+}
 
-    let new_state = PomodoroState {
-        break_duration: new_duration,
-        remaining: if guard.is_break {
-            new_duration
-        } else {
-            guard.remaining
-        },
-        ..guard.clone()
-    };
-    drop(guard);
+#[tauri::command(rename_all = "snake_case")]
+pub fn get_pomodoro_settings(settings_state: State<SettingsState>) -> AppResult<PomodoroSettings> {
+    settings_state
+        .settings
+        .lock()
+        .map_err(AppError::from)
+        .map(|settings| settings.pomodoro.clone())
+}
 
-    *state.timer.lock().unwrap() = new_state.clone();
-    new_state
+#[tauri::command(rename_all = "snake_case")]
+pub fn update_pomodoro_settings(
+    settings: PomodoroSettings,
+    state: State<TimerState>,
+    settings_state: State<SettingsState>,
+) -> AppResult<PomodoroSettings> {
+    update_pomodoro_settings_with(&state, &settings_state, |_| settings.clone())?;
+    Ok(settings)
 }
 
 #[tauri::command(rename_all = "snake_case")]
-pub fn is_timer_finished(state: State<TimerState>) -> bool {
-    is_finished(&state.timer.lock().unwrap())
+pub fn set_active_note(
+    note_id: Option<String>,
+    state: State<TimerState>,
+    vaults: State<VaultsState>,
+) -> AppResult<PomodoroState> {
+    if let Some(id) = note_id.as_deref() {
+        if !notes::commands::note_exists(id, &*vaults.active()?)? {
+            return Err(AppError::not_found("Note not found").with_context(id));
+        }
+    }
+
+    modify_timer_state(&state, |s, _| PomodoroState {
+        active_note_id: note_id,
+        ..s.clone()
+    })
 }
 
+#[tauri::command(rename_all = "snake_case")]
+pub fn is_timer_finished(state: State<TimerState>) -> AppResult<bool> {
+    let guard = state.timer.lock()?;
+    Ok(is_finished(&tick(&guard, Instant::now())))
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub fn get_session_history(from: u64, to: u64) -> AppResult<Vec<SessionRecord>> {
+    history::load_records(from, to)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub fn get_sessions_for_day(date: String) -> AppResult<Vec<SessionRecord>> {
+    history::load_records_for_day(&date)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> PomodoroSettings {
+        PomodoroSettings {
+            work_minutes: 25,
+            break_minutes: 5,
+            long_break_minutes: 15,
+            sessions_before_long_break: 2,
+            ..PomodoroSettings::default()
+        }
+    }
+
+    fn secs(seconds: u64) -> Duration {
+        Duration::from_secs(seconds)
+    }
+
+    /// Advances the timer just past the end of the running phase.
+    fn finish(state: &PomodoroState) -> (PomodoroState, PhaseFinished) {
+        let late = state.deadline.unwrap() + Duration::from_millis(300);
+        let (next, finished) = advance(state, late);
+        (next, finished.unwrap())
+    }
+
+    #[test]
+    fn test_long_break_cycle() {
+        let start = Instant::now();
+        let work = start_work_timer(&initial_state(&settings()), start);
+
+        let (first_break, finished) = finish(&work);
+        assert_eq!(finished.kind, PhaseKind::Work);
+        assert_eq!(phase_kind(&first_break), PhaseKind::Break);
+        assert_eq!(first_break.completed_sessions, 1);
+        assert_eq!(first_break.remaining, 5 * 60);
+
+        let (second_work, finished) = finish(&first_break);
+        assert!(finished.was_break);
+        assert_eq!(phase_kind(&second_work), PhaseKind::Work);
+
+        let (long_break, _) = finish(&second_work);
+        assert_eq!(phase_kind(&long_break), PhaseKind::LongBreak);
+        assert_eq!(long_break.completed_sessions, 2);
+        assert_eq!(long_break.remaining, 15 * 60);
+
+        let (next_cycle, finished) = finish(&long_break);
+        assert_eq!(finished.kind, PhaseKind::LongBreak);
+        assert_eq!(phase_kind(&next_cycle), PhaseKind::Work);
+        assert_eq!(next_cycle.completed_sessions, 0);
+
+        // Each phase runs from the previous deadline, not from when it was noticed
+        assert_eq!(
+            next_cycle.deadline,
+            Some(start + secs((25 + 5 + 25 + 15 + 25) * 60))
+        );
+        let ids = [&work, &first_break, &second_work, &long_break, &next_cycle]
+            .map(|state| state.phase_id);
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_deadline_and_pause() {
+        let start = Instant::now();
+        let work = start_work_timer(&initial_state(&settings()), start);
+        assert_eq!(tick(&work, start).remaining, 25 * 60);
+        assert_eq!(
+            tick(&work, start + Duration::from_millis(1500)).remaining,
+            25 * 60 - 1
+        );
+
+        let paused = pause(&work, start + secs(60));
+        assert!(paused.is_paused && paused.deadline.is_none());
+        assert_eq!(paused.remaining, 24 * 60);
+        // Time spent paused doesn't count down
+        assert_eq!(tick(&paused, start + secs(600)), paused);
+        assert_eq!(advance(&paused, start + secs(3600)).1.map(|f| f.kind), None);
+
+        let resumed = resume(&paused, start + secs(600));
+        assert_eq!(resumed.deadline, Some(start + secs(600 + 24 * 60)));
+        assert_eq!(resumed.phase_id, work.phase_id);
+        assert_eq!(resume(&resumed, start + secs(700)), resumed);
+    }
+
+    #[test]
+    fn test_abandoned_phases() {
+        let start = Instant::now();
+        let work = start_work_timer(&initial_state(&settings()), start);
+
+        // Restarting within the same second is still a new phase
+        let restarted = start_work_timer(&work, start);
+        let record = abandoned_record(&work, &restarted, start).unwrap();
+        assert!(record.interrupted);
+        assert_eq!(record.kind, PhaseKind::Work);
+
+        let nothing_ran = initial_state(&settings());
+        assert!(abandoned_record(&nothing_ran, &reset(&nothing_ran), start).is_none());
+        assert!(abandoned_record(&work, &pause(&work, start + secs(5)), start).is_none());
+
+        // Changing the running phase's duration records the time worked so far
+        let shorter = PomodoroSettings {
+            work_minutes: 20,
+            ..settings()
+        };
+        let later = start + secs(10 * 60);
+        let changed = with_settings(&work, &shorter, later);
+        assert_eq!(changed.remaining, 20 * 60);
+        let record = abandoned_record(&work, &changed, later).unwrap();
+        assert_eq!(record.actual_duration, 10 * 60);
+
+        let unrelated = PomodoroSettings {
+            alarm_volume: 10,
+            ..settings()
+        };
+        let unchanged = with_settings(&work, &unrelated, later);
+        assert!(abandoned_record(&work, &unchanged, later).is_none());
+    }
+}
@@ -0,0 +1,89 @@
+use crate::error::{AppError, AppResult, ResultExt};
+use crate::notes::storage::get_app_dir;
+use chrono::{Local, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PhaseKind {
+    Work,
+    Break,
+    LongBreak,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub kind: PhaseKind,
+    pub started_at: u64,
+    pub ended_at: u64,
+    pub planned_duration: u32,
+    pub actual_duration: u32,
+    pub interrupted: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note_id: Option<String>,
+}
+
+fn get_history_path() -> AppResult<PathBuf> {
+    get_app_dir().map(|dir| dir.join("sessions.jsonl"))
+}
+
+fn parse_records(content: &str) -> Vec<SessionRecord> {
+    // A torn last line from a crash shouldn't hide the rest of the log
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+fn in_range(record: &SessionRecord, from: u64, to: u64) -> bool {
+    record.started_at >= from && record.started_at < to
+}
+
+fn local_day_bounds(date: &str) -> AppResult<(u64, u64)> {
+    let day = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|e| AppError::invalid_input(e.to_string()).with_context(date))?;
+    let to_unix = |day: NaiveDate| {
+        day.and_hms_opt(0, 0, 0)
+            .and_then(|midnight| Local.from_local_datetime(&midnight).earliest())
+            .map(|start| start.timestamp().max(0) as u64)
+            .ok_or_else(|| AppError::invalid_input("Invalid local date").with_context(day))
+    };
+    let next_day = day
+        .succ_opt()
+        .ok_or_else(|| AppError::invalid_input("Date out of range").with_context(date))?;
+    Ok((to_unix(day)?, to_unix(next_day)?))
+}
+
+pub fn append_record(record: &SessionRecord) -> AppResult<()> {
+    let path = get_history_path()?;
+    let line = serde_json::to_string(record)?;
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| writeln!(file, "{}", line))
+        .context(path.display())
+}
+
+pub fn load_records(from: u64, to: u64) -> AppResult<Vec<SessionRecord>> {
+    let path = get_history_path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    fs::read_to_string(&path)
+        .context(path.display())
+        .map(|content| {
+            parse_records(&content)
+                .into_iter()
+                .filter(|record| in_range(record, from, to))
+                .collect()
+        })
+}
+
+pub fn load_records_for_day(date: &str) -> AppResult<Vec<SessionRecord>> {
+    local_day_bounds(date).and_then(|(from, to)| load_records(from, to))
+}
@@ -1,2 +1,3 @@
 pub mod commands;
+pub mod history;
 pub use commands::TimerState;
@@ -0,0 +1,45 @@
+pub mod model;
+pub mod storage;
+
+use crate::error::AppResult;
+use crate::notes::checkbox_parser::StateMark;
+use model::Settings;
+use std::sync::Mutex;
+
+pub struct SettingsState {
+    pub settings: Mutex<Settings>,
+}
+
+impl SettingsState {
+    pub fn new() -> Self {
+        let settings = storage::load_settings().unwrap_or_else(|e| {
+            eprintln!("Failed to load settings, using defaults: {}", e);
+            Settings::default()
+        });
+        Self {
+            settings: Mutex::new(settings),
+        }
+    }
+}
+
+pub fn checkbox_states(state: &SettingsState) -> AppResult<Vec<StateMark>> {
+    Ok(state.settings.lock()?.checkbox_states.clone())
+}
+
+pub fn auto_complete_tasks(state: &SettingsState) -> AppResult<bool> {
+    Ok(state.settings.lock()?.auto_complete_tasks)
+}
+
+/// Validates and persists a change before it becomes visible in memory,
+/// so a rejected value never reaches the running app.
+pub fn update_settings<F>(state: &SettingsState, modifier: F) -> AppResult<Settings>
+where
+    F: FnOnce(Settings) -> Settings,
+{
+    let mut guard = state.settings.lock()?;
+    let updated = modifier(guard.clone());
+    model::validate(&updated)?;
+    storage::save_settings(&updated)?;
+    *guard = updated.clone();
+    Ok(updated)
+}
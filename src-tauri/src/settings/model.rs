@@ -0,0 +1,99 @@
+use crate::error::{AppError, AppResult};
+use crate::notes::checkbox_parser::{self, StateMark};
+use serde::{Deserialize, Serialize};
+
+/// Longest a timer phase can be set to, a whole day.
+pub const MAX_PHASE_MINUTES: u32 = 24 * 60;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PomodoroSettings {
+    pub work_minutes: u32,
+    pub break_minutes: u32,
+    pub long_break_minutes: u32,
+    pub sessions_before_long_break: u32,
+    pub auto_check: bool,
+    pub alarm_enabled: bool,
+    pub alarm_volume: u8,
+    pub alarm_repeat: u32,
+}
+
+impl Default for PomodoroSettings {
+    fn default() -> Self {
+        Self {
+            work_minutes: 25,
+            break_minutes: 5,
+            long_break_minutes: 15,
+            sessions_before_long_break: 4,
+            auto_check: false,
+            alarm_enabled: true,
+            alarm_volume: 50,
+            alarm_repeat: 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub pomodoro: PomodoroSettings,
+    /// Checkbox marks and what they mean, in cycling order.
+    pub checkbox_states: Vec<StateMark>,
+    /// Whether ticking off every checkbox of a task marks it done, and
+    /// reopening one marks it open again. Applies to checkbox commands and
+    /// content saves alike, and to the task's ancestors as well.
+    pub auto_complete_tasks: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            pomodoro: PomodoroSettings::default(),
+            checkbox_states: checkbox_parser::default_states(),
+            auto_complete_tasks: false,
+        }
+    }
+}
+
+fn require_nonzero(name: &str, value: u32) -> AppResult<()> {
+    if value == 0 {
+        Err(
+            AppError::invalid_input(format!("{} must be greater than zero", name))
+                .with_context(name),
+        )
+    } else {
+        Ok(())
+    }
+}
+
+fn require_range(name: &str, value: u32, min: u32, max: u32) -> AppResult<()> {
+    if (min..=max).contains(&value) {
+        Ok(())
+    } else {
+        Err(
+            AppError::invalid_input(format!("{} must be between {} and {}", name, min, max))
+                .with_context(name),
+        )
+    }
+}
+
+pub fn validate_duration(name: &str, minutes: u32) -> AppResult<()> {
+    require_range(name, minutes, 1, MAX_PHASE_MINUTES)
+}
+
+pub fn validate_pomodoro(settings: &PomodoroSettings) -> AppResult<()> {
+    validate_duration("Work duration", settings.work_minutes)?;
+    validate_duration("Break duration", settings.break_minutes)?;
+    validate_duration("Long break duration", settings.long_break_minutes)?;
+    require_nonzero(
+        "Sessions before long break",
+        settings.sessions_before_long_break,
+    )?;
+    require_range("Alarm volume", settings.alarm_volume.into(), 0, 100)?;
+    require_range("Alarm repeat", settings.alarm_repeat, 1, 5)
+}
+
+pub fn validate(settings: &Settings) -> AppResult<()> {
+    validate_pomodoro(&settings.pomodoro)?;
+    checkbox_parser::validate_states(&settings.checkbox_states)
+}
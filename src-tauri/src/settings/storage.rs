@@ -0,0 +1,27 @@
+use super::model::{validate, Settings};
+use crate::error::{AppResult, ResultExt};
+use crate::notes::storage::{get_app_dir, write_atomic};
+use std::fs;
+use std::path::PathBuf;
+
+fn get_settings_path() -> AppResult<PathBuf> {
+    get_app_dir().map(|dir| dir.join("settings.json"))
+}
+
+pub fn load_settings() -> AppResult<Settings> {
+    let path = get_settings_path()?;
+    if !path.exists() {
+        return Ok(Settings::default());
+    }
+
+    let settings: Settings = fs::read_to_string(&path)
+        .context(path.display())
+        .and_then(|content| serde_json::from_str(&content).context(path.display()))?;
+    validate(&settings).map(|_| settings)
+}
+
+pub fn save_settings(settings: &Settings) -> AppResult<()> {
+    let path = get_settings_path()?;
+    let content = serde_json::to_string_pretty(settings)?;
+    write_atomic(&path, &content)
+}
@@ -0,0 +1,63 @@
+use super::model::{active_name, Vault, VaultConfig, VaultEntry, DEFAULT_VAULT};
+use super::VaultsState;
+use crate::error::{AppError, AppResult};
+use tauri::State;
+
+#[tauri::command(rename_all = "snake_case")]
+pub fn list_vaults(vaults: State<VaultsState>) -> AppResult<Vec<VaultEntry>> {
+    vaults.entries()
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub fn add_vault(
+    name: String,
+    path: String,
+    vaults: State<VaultsState>,
+) -> AppResult<Vec<VaultEntry>> {
+    vaults.update(|config| VaultConfig {
+        vaults: [config.vaults, vec![Vault { name, path }]].concat(),
+        ..config
+    })?;
+    vaults.entries()
+}
+
+/// Opens another vault and reloads its notes; the frontend should fetch
+/// notes again afterwards.
+#[tauri::command(rename_all = "snake_case")]
+pub fn switch_vault(name: String, vaults: State<VaultsState>) -> AppResult<Vec<VaultEntry>> {
+    // Validation rejects an unknown name before anything is reloaded
+    vaults.update(|config| VaultConfig {
+        active: (name != DEFAULT_VAULT).then(|| name.clone()),
+        ..config
+    })?;
+    vaults.entries()
+}
+
+/// Forgets a vault without touching its files. Removing the open vault
+/// switches back to the default one.
+#[tauri::command(rename_all = "snake_case")]
+pub fn remove_vault(name: String, vaults: State<VaultsState>) -> AppResult<Vec<VaultEntry>> {
+    if name == DEFAULT_VAULT {
+        return Err(AppError::invalid_input(
+            "The default vault can't be removed",
+        ));
+    }
+
+    if !vaults.entries()?.iter().any(|vault| vault.name == name) {
+        return Err(AppError::not_found("Vault not found").with_context(&name));
+    }
+
+    vaults.update(|config| VaultConfig {
+        active: config
+            .active
+            .clone()
+            .filter(|_| active_name(&config) != name),
+        vaults: config
+            .vaults
+            .into_iter()
+            .filter(|vault| vault.name != name)
+            .collect(),
+        ..config
+    })?;
+    vaults.entries()
+}
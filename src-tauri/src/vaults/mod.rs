@@ -0,0 +1,105 @@
+pub mod commands;
+pub mod model;
+pub mod storage;
+
+use crate::error::AppResult;
+use crate::notes::{watcher, NotesState};
+use model::{active_name, validate, Vault, VaultConfig, VaultEntry, DEFAULT_VAULT};
+use std::sync::{Arc, Mutex, OnceLock};
+use tauri::AppHandle;
+
+/// Known vaults plus the notes of the one that's open. Switching swaps in a
+/// freshly loaded `NotesState`; callers holding the old one finish on it.
+pub struct VaultsState {
+    config: Mutex<VaultConfig>,
+    /// Why no vault could be opened, e.g. no home directory and none set up,
+    /// until a switch to one that can.
+    active: Mutex<AppResult<Arc<NotesState>>>,
+    /// Handle for the watchers' events, available once the app is set up.
+    app: OnceLock<AppHandle>,
+}
+
+impl VaultsState {
+    pub fn new() -> Self {
+        let config = storage::load_config().unwrap_or_else(|e| {
+            eprintln!("Failed to load vaults, using the default one: {}", e);
+            VaultConfig::default()
+        });
+        let active = storage::vault_dir(&config, active_name(&config))
+            .or_else(|e| {
+                eprintln!("Failed to open vault, using the default one: {}", e);
+                storage::vault_dir(&config, DEFAULT_VAULT)
+            })
+            .map(|dir| Arc::new(NotesState::load(dir)));
+
+        Self {
+            config: Mutex::new(config),
+            active: Mutex::new(active),
+            app: OnceLock::new(),
+        }
+    }
+
+    /// Starts watching the open vault, and every vault opened after it.
+    pub fn attach(&self, app: AppHandle) -> AppResult<()> {
+        let _ = self.app.set(app);
+        if let Ok(notes) = &*self.active.lock()? {
+            self.watch(notes);
+        }
+        Ok(())
+    }
+
+    fn watch(&self, notes: &Arc<NotesState>) {
+        if let Some(app) = self.app.get() {
+            if let Err(e) = watcher::watch(app.clone(), notes) {
+                eprintln!("Failed to watch notes directory: {}", e);
+            }
+        }
+    }
+
+    pub fn active(&self) -> AppResult<Arc<NotesState>> {
+        self.active.lock()?.clone()
+    }
+
+    pub fn entries(&self) -> AppResult<Vec<VaultEntry>> {
+        let config = self.config.lock()?;
+        // Still listed when its directory can't be resolved, so the other
+        // vaults stay reachable
+        let default = Vault {
+            name: DEFAULT_VAULT.to_string(),
+            path: storage::vault_dir(&config, DEFAULT_VAULT)
+                .map(|dir| dir.display().to_string())
+                .unwrap_or_default(),
+        };
+
+        Ok(std::iter::once(default)
+            .chain(config.vaults.iter().cloned())
+            .map(|vault| VaultEntry {
+                is_active: vault.name == active_name(&config),
+                name: vault.name,
+                path: vault.path,
+            })
+            .collect())
+    }
+
+    /// Validates and persists a change before it becomes visible in memory,
+    /// then reopens the active vault if the change moved or replaced it.
+    pub fn update<F>(&self, modifier: F) -> AppResult<()>
+    where
+        F: FnOnce(VaultConfig) -> VaultConfig,
+    {
+        let mut config = self.config.lock()?;
+        let updated = modifier(config.clone());
+        validate(&updated)?;
+
+        let dir = storage::vault_dir(&updated, active_name(&updated))?;
+        let mut active = self.active.lock()?;
+        storage::save_config(&updated)?;
+        if !active.as_ref().is_ok_and(|notes| notes.dir == dir) {
+            let notes = Arc::new(NotesState::load(dir));
+            self.watch(&notes);
+            *active = Ok(notes);
+        }
+        *config = updated;
+        Ok(())
+    }
+}
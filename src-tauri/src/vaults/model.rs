@@ -0,0 +1,76 @@
+use crate::error::{AppError, AppResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Name of the built-in vault, which always exists and can't be removed.
+pub const DEFAULT_VAULT: &str = "default";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Vault {
+    pub name: String,
+    pub path: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VaultConfig {
+    /// Overrides `~/.tomatxt/notes` as the location of the default vault.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes_dir: Option<String>,
+    pub vaults: Vec<Vault>,
+    /// Name of the open vault, `None` for the default one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active: Option<String>,
+}
+
+/// A vault as shown to the frontend, with its resolved location.
+#[derive(Clone, Debug, Serialize)]
+pub struct VaultEntry {
+    pub name: String,
+    pub path: String,
+    pub is_active: bool,
+}
+
+pub fn active_name(config: &VaultConfig) -> &str {
+    config.active.as_deref().unwrap_or(DEFAULT_VAULT)
+}
+
+pub fn find_vault<'a>(config: &'a VaultConfig, name: &str) -> Option<&'a Vault> {
+    config.vaults.iter().find(|vault| vault.name == name)
+}
+
+fn validate_vault(vault: &Vault) -> AppResult<()> {
+    if vault.name.trim().is_empty() {
+        return Err(AppError::invalid_input("Vault name must not be empty"));
+    }
+    if vault.name == DEFAULT_VAULT {
+        return Err(AppError::invalid_input("Vault name is reserved").with_context(&vault.name));
+    }
+    if !Path::new(&vault.path).is_absolute() {
+        return Err(
+            AppError::invalid_input("Vault path must be absolute").with_context(&vault.path)
+        );
+    }
+    Ok(())
+}
+
+pub fn validate(config: &VaultConfig) -> AppResult<()> {
+    config.vaults.iter().try_for_each(validate_vault)?;
+
+    let mut names = HashSet::new();
+    if let Some(duplicate) = config
+        .vaults
+        .iter()
+        .find(|vault| !names.insert(vault.name.as_str()))
+    {
+        return Err(AppError::invalid_input("Vault already exists").with_context(&duplicate.name));
+    }
+
+    match config.active.as_deref() {
+        Some(name) if find_vault(config, name).is_none() => {
+            Err(AppError::not_found("Vault not found").with_context(name))
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,60 @@
+use super::model::{find_vault, validate, VaultConfig, DEFAULT_VAULT};
+use crate::error::{AppError, AppResult, ResultExt};
+use crate::notes::storage::{create_notes_dir, get_app_dir, get_notes_dir, write_atomic};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Points the default vault elsewhere without touching the config file.
+pub const NOTES_DIR_ENV: &str = "TOMATXT_NOTES_DIR";
+
+fn get_config_path() -> AppResult<PathBuf> {
+    get_app_dir().map(|dir| dir.join("vaults.json"))
+}
+
+pub fn load_config() -> AppResult<VaultConfig> {
+    let path = get_config_path()?;
+    if !path.exists() {
+        return Ok(VaultConfig::default());
+    }
+
+    let config: VaultConfig = fs::read_to_string(&path)
+        .context(path.display())
+        .and_then(|content| serde_json::from_str(&content).context(path.display()))?;
+    validate(&config).map(|_| config)
+}
+
+pub fn save_config(config: &VaultConfig) -> AppResult<()> {
+    let path = get_config_path()?;
+    let content = serde_json::to_string_pretty(config)?;
+    write_atomic(&path, &content)
+}
+
+/// The environment variable wins over the config file, which wins over
+/// `~/.tomatxt/notes`.
+fn default_vault_dir(config: &VaultConfig) -> AppResult<PathBuf> {
+    match env::var(NOTES_DIR_ENV)
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .or_else(|| config.notes_dir.clone())
+    {
+        Some(dir) => {
+            let dir = PathBuf::from(dir);
+            create_notes_dir(&dir)?;
+            Ok(dir)
+        }
+        None => get_notes_dir(),
+    }
+}
+
+pub fn vault_dir(config: &VaultConfig, name: &str) -> AppResult<PathBuf> {
+    if name == DEFAULT_VAULT {
+        return default_vault_dir(config);
+    }
+
+    let vault = find_vault(config, name)
+        .ok_or_else(|| AppError::not_found("Vault not found").with_context(name))?;
+    let dir = PathBuf::from(&vault.path);
+    create_notes_dir(&dir)?;
+    Ok(dir)
+}
@@ -1,8 +1,18 @@
 const { invoke } = window.__TAURI__.core;
+const { listen } = window.__TAURI__.event;
+
+const NOTES_PAGE_SIZE = 50;
 
 let currentNote = null;
-let timerInterval = null;
 let currentState = null;
+let searchQuery = '';
+let currentTag = null;
+let currentDueView = null;
+// { name } untuk query tersimpan, { query } untuk query yang diketik
+let currentQuery = null;
+let searchTimeout = null;
+let currentSort = 'created_at:asc';
+let notesShown = NOTES_PAGE_SIZE;
 const noteColors = ['blue', 'lilac', 'mint', 'cream', 'pink', 'sand'];
 
 function getRandomNoteColor() {
@@ -13,7 +23,13 @@ function getRandomNoteColor() {
 
 async function initApp() {
         try {
+                await loadVaults();
+                await loadSavedQueries();
                 await loadAllNotes();
+                await reportUnreadableNotes();
+                await listen('notes://changed', event => handleExternalChange(event.payload));
+                await listen('notes://reminder', event => showReminder(event.payload));
+                await listen('notes://error', event => showNotification(event.payload.message, 'error'));
 
                 setupEventListeners();
                 setupPomodoroEventListeners();
@@ -25,12 +41,113 @@ async function initApp() {
         }
 }
 
+// File yang rusak tetap di disk, cukup beri tahu pengguna
+async function reportUnreadableNotes() {
+        try {
+                reportScanProblems(await invoke('get_unreadable_notes'), await invoke('get_scan_failures'));
+        } catch (error) {
+                // Biasanya karena tidak ada vault yang bisa dibuka
+                showNotification(error.message ?? error, 'error');
+        }
+}
+
+function reportScanProblems(unreadable, failures) {
+        if (unreadable.length > 0) {
+                const paths = unreadable.map(note => note.path).join('\n');
+                showNotification(`Some notes could not be read:\n${paths}`, 'error');
+        }
+        // Catatannya tetap terbaca, hanya migrasinya yang gagal
+        if (failures.length > 0) {
+                const details = failures.map(failure => `${failure.path}: ${failure.error.message}`).join('\n');
+                showNotification(`Some notes could not be updated on disk:\n${details}`, 'error');
+        }
+}
+
 document.addEventListener('DOMContentLoaded', () => {
         setTimeout(initApp, 100);
 });
 
 
 function setupEventListeners() {
+        const vaultSelect = document.getElementById('vault-select');
+        if (vaultSelect) {
+                vaultSelect.addEventListener('change', () => switchVault(vaultSelect.value));
+        }
+
+        // Cari sambil mengetik, tunggu jeda sebentar
+        const searchInput = document.getElementById('note-search');
+        if (searchInput) {
+                searchInput.addEventListener('input', () => {
+                        clearTimeout(searchTimeout);
+                        searchTimeout = setTimeout(() => {
+                                searchQuery = searchInput.value.trim();
+                                loadAllNotes();
+                        }, 200);
+                });
+        }
+
+        const sortSelect = document.getElementById('note-sort');
+        if (sortSelect) {
+                sortSelect.addEventListener('change', () => {
+                        currentSort = sortSelect.value;
+                        notesShown = NOTES_PAGE_SIZE;
+                        loadAllNotes();
+                });
+        }
+
+        document.querySelectorAll('[data-due-view]').forEach(btn => {
+                btn.addEventListener('click', () => showDueView(btn.dataset.dueView));
+        });
+
+        // Query dijalankan saat Enter, dikosongkan untuk kembali ke semua note
+        const queryInput = document.getElementById('query-input');
+        if (queryInput) {
+                queryInput.addEventListener('keydown', event => {
+                        if (event.key !== 'Enter') return;
+                        const query = queryInput.value.trim();
+                        showQuery(query ? { query } : null);
+                });
+                queryInput.addEventListener('search', () => {
+                        if (!queryInput.value.trim()) showQuery(null);
+                });
+        }
+        const saveQueryBtn = document.getElementById('save-query-btn');
+        if (saveQueryBtn) saveQueryBtn.addEventListener('click', saveCurrentQuery);
+
+        // Tanggal tersimpan begitu diganti
+        ['detail-due', 'detail-scheduled'].forEach(id => {
+                const input = document.getElementById(id);
+                if (input) input.addEventListener('change', () => saveSchedule());
+        });
+        ['detail-is-task', 'detail-is-done'].forEach(id => {
+                const input = document.getElementById(id);
+                if (input) input.addEventListener('change', () => saveTaskStatus());
+        });
+        ['detail-recurrence', 'detail-reset-checkboxes'].forEach(id => {
+                const input = document.getElementById(id);
+                if (input) input.addEventListener('change', () => saveRecurrence());
+        });
+        // Pengaturan global: is_done mengikuti checkbox
+        const autoComplete = document.getElementById('auto-complete-tasks');
+        if (autoComplete) {
+                invoke('get_auto_complete_tasks')
+                        .then(enabled => { autoComplete.checked = enabled; })
+                        .catch(error => console.error('Failed to load auto-complete setting:', error));
+                autoComplete.addEventListener('change', async () => {
+                        try {
+                                await invoke('set_auto_complete_tasks', { enabled: autoComplete.checked });
+                        } catch (error) {
+                                console.error('Failed to save auto-complete setting:', error);
+                                autoComplete.checked = !autoComplete.checked;
+                        }
+                });
+        }
+
+        const addReminderBtn = document.getElementById('add-reminder-btn');
+        if (addReminderBtn) {
+                addReminderBtn.addEventListener('click', addReminder);
+        }
+
         // Add new note button
         const addBtn = document.querySelector('button.btn-primary');
         if (addBtn) {
@@ -48,6 +165,12 @@ function setupEventListeners() {
                 editBtn.addEventListener('click', toggleNoteEdit);
         }
 
+        // Child note kembali menjadi checkbox di induknya
+        const demoteBtn = document.getElementById('demote-note-btn');
+        if (demoteBtn) {
+                demoteBtn.addEventListener('click', demoteCurrentNote);
+        }
+
         // Subnote form submission
         document.addEventListener('submit', function (event) {
                 if (event.target.id === 'subnote-form') {
@@ -95,6 +218,7 @@ function setupPomodoroEventListeners() {
         const settingsForm = document.getElementById('pomodoro-settings');
         const workTab = document.querySelector('[data-timer-tab="work"]');
         const breakTab = document.querySelector('[data-timer-tab="break"]');
+        const longBreakTab = document.querySelector('[data-timer-tab="long_break"]');
 
         if (playBtn) playBtn.addEventListener('click', toggleTimer);
         if (resetBtn) resetBtn.addEventListener('click', handleReset);
@@ -102,6 +226,7 @@ function setupPomodoroEventListeners() {
         if (settingsForm) settingsForm.addEventListener('submit', handleSettingsSubmit);
         if (workTab) workTab.addEventListener('click', () => switchTab('work'));
         if (breakTab) breakTab.addEventListener('click', () => switchTab('break'));
+        if (longBreakTab) longBreakTab.addEventListener('click', () => switchTab('long_break'));
 
         // Volume slider
         const volumeSlider = document.getElementById('alarm-volume');
@@ -114,13 +239,355 @@ function setupPomodoroEventListeners() {
 }
 
 
+function renderVaults(vaults) {
+        const select = document.getElementById('vault-select');
+        if (!select) return;
+
+        select.innerHTML = vaults
+                .map(vault => `<option value="${escapeHtml(vault.name)}" title="${escapeHtml(vault.path)}"${vault.is_active ? ' selected' : ''}>${escapeHtml(vault.name)}</option>`)
+                .join('');
+        // Hanya tampil kalau ada lebih dari satu vault
+        select.hidden = vaults.length < 2;
+}
+
+async function loadVaults() {
+        try {
+                renderVaults(await invoke('list_vaults'));
+        } catch (error) {
+                console.error('Failed to load vaults:', error);
+        }
+}
+
+async function switchVault(name) {
+        try {
+                renderVaults(await invoke('switch_vault', { name }));
+                currentState = await invoke('set_active_note', { note_id: null });
+                // Query tersimpan ikut vault
+                currentQuery = null;
+                await loadSavedQueries();
+                await loadAllNotes();
+                await reportUnreadableNotes();
+        } catch (error) {
+                console.error('Failed to switch vault:', error);
+                showNotification(error.message ?? error, 'error');
+                await loadVaults();
+        }
+}
+
+// File note diubah dari luar (vim, VS Code, ...)
+async function handleExternalChange({ changed, removed, unreadable, failures }) {
+        reportScanProblems(unreadable, failures);
+        await loadAllNotes();
+        if (!currentNote) return;
+
+        if (removed.includes(currentNote.id)) {
+                showNotification('This note was deleted outside the app', 'error');
+                closeNoteDetail();
+                return;
+        }
+        if (!changed.includes(currentNote.id)) return;
+
+        // Jangan timpa editan yang belum disimpan tanpa bertanya
+        const isEditing = document.querySelector('[data-note-edit]').classList.contains('is-editing');
+        if (isEditing && !confirm('This note was changed outside the app. Discard your unsaved edits and load the new version?')) {
+                return;
+        }
+        await openNoteDetail(currentNote.id);
+}
+
 async function loadAllNotes() {
         try {
-                const notes = await invoke('get_notes');
-                renderNotes(notes);
+                if (searchQuery) {
+                        const hits = await invoke('search_notes', { query: searchQuery });
+                        renderSearchHits(hits);
+                        return;
+                }
+                if (currentQuery) {
+                        renderNotes(currentQuery.name
+                                ? await invoke('run_saved_query', { name: currentQuery.name })
+                                : await invoke('run_query', { query: currentQuery.query }));
+                        return;
+                }
+                if (currentDueView === 'items') {
+                        renderOpenItems(await invoke('get_open_items', { filter: null }));
+                        return;
+                }
+                if (currentDueView) {
+                        renderNotes(await invoke(dueViewCommands[currentDueView]));
+                        return;
+                }
+                const [sort, direction] = currentSort.split(':');
+                const page = await invoke('get_notes', {
+                        query: {
+                                tag: currentTag,
+                                // Tag bisa ada di child note juga
+                                include_children: currentTag !== null,
+                                sort,
+                                direction,
+                                limit: notesShown,
+                        },
+                });
+                await renderNotes(page.notes);
+                renderLoadMore(page.total - page.notes.length);
+                await loadTags();
         } catch (error) {
                 console.error('Failed to load notes:', error);
-                showNotification('Failed to load notes', 'error');
+                // Query yang salah ketik perlu pesan yang jelas
+                showNotification(currentQuery ? (error.message ?? error) : 'Failed to load notes', 'error');
+        }
+}
+
+const dueViewCommands = {
+        overdue: 'get_overdue_notes',
+        today: 'get_notes_due_today',
+        upcoming: 'get_upcoming_notes',
+};
+
+function showDueView(view) {
+        currentDueView = currentDueView === view ? null : view;
+        document.querySelectorAll('[data-due-view]').forEach(btn => {
+                btn.classList.toggle('is-active', btn.dataset.dueView === currentDueView);
+        });
+        loadAllNotes();
+}
+
+function showReminder({ title, due }) {
+        showNotification(due ? `Reminder: ${title} (due ${due})` : `Reminder: ${title}`);
+}
+
+// Tanggal lokal hari ini dalam format YYYY-MM-DD
+function todayString() {
+        const now = new Date();
+        return new Date(now.getTime() - now.getTimezoneOffset() * 60000).toISOString().slice(0, 10);
+}
+
+function renderSchedule(note) {
+        document.getElementById('detail-due').value = note.due ?? '';
+        document.getElementById('detail-scheduled').value = note.scheduled ?? '';
+        document.getElementById('detail-reminder').value = '';
+        document.getElementById('detail-is-task').checked = note.is_task;
+        document.getElementById('detail-is-done').checked = note.is_done;
+        document.getElementById('detail-is-done').disabled = !note.is_task;
+        document.getElementById('detail-recurrence').value = note.recurrence ?? '';
+        document.getElementById('detail-reset-checkboxes').checked = note.reset_checkboxes;
+
+        const list = document.getElementById('reminder-list');
+        list.innerHTML = note.reminders
+                .map(at => `<li class="tag-chip">⏰ ${escapeHtml(new Date(at * 1000).toLocaleString())} <button type="button" class="icon-btn" data-remove-reminder="${at}" aria-label="Hapus pengingat">✕</button></li>`)
+                .join('');
+        list.querySelectorAll('[data-remove-reminder]').forEach(btn => {
+                const at = Number(btn.dataset.removeReminder);
+                btn.addEventListener('click', () => saveSchedule(currentNote.reminders.filter(r => r !== at)));
+        });
+}
+
+function addReminder() {
+        const value = document.getElementById('detail-reminder').value;
+        if (!value || !currentNote) return;
+
+        const at = Math.floor(new Date(value).getTime() / 1000);
+        saveSchedule([...currentNote.reminders, at]);
+}
+
+async function saveSchedule(reminders = currentNote?.reminders) {
+        if (!currentNote) return;
+
+        try {
+                currentNote = await invoke('set_note_schedule', {
+                        id: currentNote.id,
+                        due: document.getElementById('detail-due').value || null,
+                        scheduled: document.getElementById('detail-scheduled').value || null,
+                        reminders,
+                        expected_revision: currentNote.revision,
+                });
+                renderSchedule(currentNote);
+                await loadAllNotes();
+        } catch (error) {
+                console.error('Failed to save schedule:', error);
+                if (!(await resolveConflict(error))) {
+                        showNotification(error.message ?? error, 'error');
+                }
+        }
+}
+
+async function saveTaskStatus() {
+        if (!currentNote) return;
+
+        try {
+                const { note, next } = await invoke('set_task_status', {
+                        id: currentNote.id,
+                        is_task: document.getElementById('detail-is-task').checked,
+                        is_done: document.getElementById('detail-is-done').checked,
+                        expected_revision: currentNote.revision,
+                });
+                currentNote = note;
+                renderSchedule(note);
+                await loadAllNotes();
+                if (next) {
+                        showNotification(`Next occurrence created, due ${next.due}`);
+                }
+        } catch (error) {
+                console.error('Failed to update task status:', error);
+                if (!(await resolveConflict(error))) {
+                        showNotification(error.message ?? error, 'error');
+                }
+        }
+}
+
+async function saveRecurrence() {
+        if (!currentNote) return;
+
+        try {
+                currentNote = await invoke('set_note_recurrence', {
+                        id: currentNote.id,
+                        recurrence: document.getElementById('detail-recurrence').value || null,
+                        reset_checkboxes: document.getElementById('detail-reset-checkboxes').checked,
+                        expected_revision: currentNote.revision,
+                });
+                renderSchedule(currentNote);
+        } catch (error) {
+                console.error('Failed to save recurrence:', error);
+                if (!(await resolveConflict(error))) {
+                        showNotification(error.message ?? error, 'error');
+                }
+        }
+}
+
+function showQuery(query) {
+        currentQuery = query;
+        document.querySelectorAll('[data-saved-query]').forEach(chip => {
+                chip.classList.toggle('is-active', chip.dataset.savedQuery === currentQuery?.name);
+        });
+        loadAllNotes();
+}
+
+async function loadSavedQueries() {
+        try {
+                renderSavedQueries(await invoke('list_saved_queries'));
+        } catch (error) {
+                console.error('Failed to load saved queries:', error);
+        }
+}
+
+function renderSavedQueries(queries) {
+        const container = document.getElementById('saved-queries');
+        if (!container) return;
+
+        container.innerHTML = queries
+                .map(saved => `<button type="button" class="tag-chip${saved.name === currentQuery?.name ? ' is-active' : ''}" data-saved-query="${escapeHtml(saved.name)}" data-query="${escapeHtml(saved.query)}" title="${escapeHtml(saved.query)} — klik dua kali untuk menghapus">${escapeHtml(saved.name)}</button>`)
+                .join('');
+
+        container.querySelectorAll('[data-saved-query]').forEach(chip => {
+                chip.addEventListener('click', () => {
+                        const name = chip.dataset.savedQuery;
+                        const queryInput = document.getElementById('query-input');
+                        if (currentQuery?.name === name) {
+                                if (queryInput) queryInput.value = '';
+                                showQuery(null);
+                                return;
+                        }
+                        if (queryInput) queryInput.value = chip.dataset.query;
+                        showQuery({ name });
+                });
+                chip.addEventListener('dblclick', () => deleteSavedQuery(chip.dataset.savedQuery));
+        });
+}
+
+async function saveCurrentQuery() {
+        const query = document.getElementById('query-input')?.value.trim();
+        if (!query) {
+                showNotification('Type a query to save first', 'error');
+                return;
+        }
+        const name = prompt('Save this query as:', currentQuery?.name ?? '');
+        if (!name) return;
+
+        try {
+                renderSavedQueries(await invoke('save_query', { name, query }));
+                showQuery({ name: name.trim() });
+        } catch (error) {
+                console.error('Failed to save query:', error);
+                showNotification(error.message ?? error, 'error');
+        }
+}
+
+async function deleteSavedQuery(name) {
+        if (!confirm(`Delete the saved query "${name}"?`)) return;
+
+        try {
+                if (currentQuery?.name === name) currentQuery = null;
+                renderSavedQueries(await invoke('delete_saved_query', { name }));
+                await loadAllNotes();
+        } catch (error) {
+                console.error('Failed to delete saved query:', error);
+                showNotification(error.message ?? error, 'error');
+        }
+}
+
+async function loadTags() {
+        try {
+                renderTags(await invoke('list_tags'));
+        } catch (error) {
+                console.error('Failed to load tags:', error);
+        }
+}
+
+function renderTags(tags) {
+        const tagBar = document.getElementById('tag-bar');
+        if (!tagBar) return;
+
+        // Tag yang dipilih hilang (mis. sudah di-rename), tampilkan semua lagi
+        if (currentTag && !tags.some(tag => tag.name === currentTag)) {
+                currentTag = null;
+                loadAllNotes();
+                return;
+        }
+
+        tagBar.hidden = tags.length === 0;
+        tagBar.innerHTML = tags
+                .map(tag => `<button type="button" class="tag-chip${tag.name === currentTag ? ' is-active' : ''}" data-tag="${escapeHtml(tag.name)}" title="Klik dua kali untuk mengganti nama">#${escapeHtml(tag.name)}<span class="tag-chip__count">${tag.count}</span></button>`)
+                .join('');
+
+        tagBar.querySelectorAll('[data-tag]').forEach(chip => {
+                chip.addEventListener('click', () => filterByTag(chip.dataset.tag));
+                chip.addEventListener('dblclick', () => renameTag(chip.dataset.tag));
+        });
+}
+
+function filterByTag(tag) {
+        currentTag = currentTag === tag ? null : tag;
+        notesShown = NOTES_PAGE_SIZE;
+        loadAllNotes();
+}
+
+// Tombol "muat lagi" bila masih ada note di halaman berikutnya
+function renderLoadMore(remaining) {
+        const notesGrid = document.querySelector('.notes-grid');
+        if (!notesGrid || remaining <= 0) return;
+
+        const button = document.createElement('button');
+        button.type = 'button';
+        button.className = 'btn-sm notes-load-more';
+        button.textContent = `Muat ${Math.min(remaining, NOTES_PAGE_SIZE)} lagi (${remaining} tersisa)`;
+        button.addEventListener('click', () => {
+                notesShown += NOTES_PAGE_SIZE;
+                loadAllNotes();
+        });
+        notesGrid.appendChild(button);
+}
+
+async function renameTag(tag) {
+        const name = prompt(`Rename #${tag} to:`, tag);
+        if (!name || name === tag) return;
+
+        try {
+                await invoke('rename_tag', { from: tag, to: name });
+                if (currentTag === tag) currentTag = null;
+                await loadAllNotes();
+                if (currentNote) await openNoteDetail(currentNote.id);
+        } catch (error) {
+                console.error('Failed to rename tag:', error);
+                showNotification(error.message ?? error, 'error');
         }
 }
 
@@ -136,6 +603,82 @@ async function renderNotes(notes) {
         }
 }
 
+// Badge untuk metadata inline: due, prioritas, estimasi, @konteks, +proyek
+function checkboxMetaBadges(meta) {
+        const badges = [];
+        if (meta.due) {
+                const overdue = meta.due < todayString();
+                badges.push(`<span class="item-badge${overdue ? ' is-overdue' : ''}">📅 ${escapeHtml(meta.due)}</span>`);
+        }
+        if (meta.priority) badges.push(`<span class="item-badge item-badge--${meta.priority}">!${meta.priority}</span>`);
+        if (meta.estimate) badges.push(`<span class="item-badge">${meta.estimate}🍅</span>`);
+        meta.contexts.forEach(context => badges.push(`<span class="item-badge">@${escapeHtml(context)}</span>`));
+        meta.projects.forEach(project => badges.push(`<span class="item-badge">+${escapeHtml(project)}</span>`));
+        return badges.join('');
+}
+
+function renderOpenItems(items) {
+        const notesGrid = document.querySelector('.notes-grid');
+        if (!notesGrid) return;
+
+        notesGrid.innerHTML = '';
+
+        for (const { note_id, note_title, checkbox } of items) {
+                const item = document.createElement('article');
+                item.className = 'open-item';
+                item.innerHTML = `
+            <p class="open-item__title">[${escapeHtml(checkbox.mark)}] ${escapeHtml(checkbox.title || checkbox.text)}</p>
+            <p class="open-item__note">${escapeHtml(note_title)}</p>
+            <div class="open-item__meta">${checkboxMetaBadges(checkbox)}</div>
+        `;
+                item.addEventListener('click', () => openNoteDetail(note_id));
+                notesGrid.appendChild(item);
+        }
+
+        if (items.length === 0) {
+                notesGrid.innerHTML = '<p class="notes-empty">Tidak ada item terbuka.</p>';
+        }
+}
+
+async function renderSearchHits(hits) {
+        const notesGrid = document.querySelector('.notes-grid');
+        if (!notesGrid) return;
+
+        notesGrid.innerHTML = '';
+
+        for (const hit of hits) {
+                const noteElement = await createNoteElement(hit.note);
+                noteElement.querySelector('h2').innerHTML = highlightText(hit.note.title, titleHighlights(hit));
+                const body = hit.snippets.filter(snippet => snippet.field !== 'title');
+                if (body.length > 0) {
+                        noteElement.querySelector('p').innerHTML = body
+                                .map(snippet => highlightText(snippet.text, snippet.highlights))
+                                .join('<br>');
+                }
+                notesGrid.appendChild(noteElement);
+        }
+
+        if (hits.length === 0) {
+                notesGrid.innerHTML = '<p class="notes-empty">Tidak ada catatan yang cocok.</p>';
+        }
+}
+
+function titleHighlights(hit) {
+        const title = hit.snippets.find(snippet => snippet.field === 'title');
+        return title ? title.highlights : [];
+}
+
+// Offset highlight dalam UTF-16, sama dengan indeks string JS
+function highlightText(text, highlights) {
+        let html = '';
+        let last = 0;
+        for (const { start, end } of highlights) {
+                html += escapeHtml(text.slice(last, start)) + `<mark>${escapeHtml(text.slice(start, end))}</mark>`;
+                last = end;
+        }
+        return html + escapeHtml(text.slice(last));
+}
+
 async function createNoteElement(note) {
         const article = document.createElement('article');
         article.className = `note-card note-card--${getRandomNoteColor()}`;
@@ -146,6 +689,26 @@ async function createNoteElement(note) {
                 childCountHtml = `<div class="note-card__sub-notes">📌 ${note.child_count} sub-note${note.child_count > 1 ? 's' : ''}</div>`;
         }
 
+        let progressHtml = '';
+        if (note.percent_complete !== null) {
+                progressHtml = `
+        <div class="note-card__progress" title="${note.checkbox_completed}/${note.checkbox_total} item selesai">
+            <div class="note-card__progress-bar" style="width: ${note.percent_complete}%"></div>
+        </div>`;
+        }
+
+        let dueHtml = '';
+        if (note.due) {
+                const overdue = note.is_task && !note.is_done && note.due < todayString();
+                const repeat = note.recurrence ? ` 🔁 ${escapeHtml(note.recurrence)}` : '';
+                dueHtml = `<div class="note-card__due${overdue ? ' is-overdue' : ''}">📅 ${escapeHtml(note.due)}${repeat}</div>`;
+        }
+
+        let tagsHtml = '';
+        if (note.tags.length > 0) {
+                tagsHtml = `<div class="note-card__tags">${note.tags.map(tag => `<span class="tag-chip">#${escapeHtml(tag)}</span>`).join('')}</div>`;
+        }
+
         article.innerHTML = `
         <button type="button" class="note-menu-btn" aria-label="Opsi catatan">⋮</button>
         <div class="note-menu">
@@ -168,6 +731,9 @@ async function createNoteElement(note) {
         </div>
         <h2>${escapeHtml(note.title)}</h2>
         <p>${escapeHtml(note.content_preview)}</p>
+        ${progressHtml}
+        ${dueHtml}
+        ${tagsHtml}
         ${childCountHtml}
     `;
 
@@ -211,11 +777,14 @@ async function openNoteDetail(noteId) {
         try {
                 const note = await invoke('get_note', { id: noteId });
                 currentNote = note;
+                currentState = await invoke('set_active_note', { note_id: noteId });
 
                 document.getElementById('detail-title').textContent = note.title;
                 document.getElementById('detail-description').textContent = note.content_without_checkboxes || note.content;
                 document.getElementById('detail-title-input').value = note.title;
                 document.getElementById('detail-description-input').value = note.content_without_checkboxes || note.content;
+                renderSchedule(note);
+                document.getElementById('demote-note-btn').hidden = !note.parent_id;
 
                 const subnoteList = document.getElementById('subnote-list');
                 if (subnoteList) {
@@ -297,7 +866,8 @@ async function saveCurrentNote() {
                 const updatedNote = await invoke('update_note', {
                         id: currentNote.id,
                         title: document.getElementById('detail-title-input').value,
-                        content: document.getElementById('detail-description-input').value
+                        content: document.getElementById('detail-description-input').value,
+                        expected_revision: currentNote.revision
                 });
 
                 currentNote = updatedNote;
@@ -314,11 +884,40 @@ async function saveCurrentNote() {
                 showNotification('Note saved successfully', 'success');
                 toggleEditState(false);
         } catch (error) {
+                if (await resolveConflict(error)) return;
                 console.error('Failed to save note:', error);
                 showNotification('Failed to save note', 'error');
         }
 }
 
+async function demoteCurrentNote() {
+        if (!currentNote?.parent_id) return;
+        if (!confirm('Turn this note back into a checkbox? Its pomodoro count and schedule will be lost.')) return;
+
+        try {
+                const parent = await invoke('demote_note', {
+                        id: currentNote.id,
+                        expected_revision: currentNote.revision,
+                });
+                await loadAllNotes();
+                await openNoteDetail(parent.id);
+        } catch (error) {
+                if (await resolveConflict(error)) return;
+                console.error('Failed to demote note:', error);
+                showNotification(error.message ?? 'Failed to turn note into a checkbox', 'error');
+        }
+}
+
+// Note berubah sejak terakhir dimuat (revision tidak cocok)
+async function resolveConflict(error) {
+        if (error?.kind !== 'conflict') return false;
+
+        if (confirm('This note was changed elsewhere. Load the latest version? Your unsaved changes will be lost.')) {
+                await openNoteDetail(currentNote.id);
+        }
+        return true;
+}
+
 async function deleteNote(noteId) {
         if (!confirm('Are you sure you want to delete this note?')) {
                 return;
@@ -360,7 +959,8 @@ async function addSubnote(event) {
                 const updatedNote = await invoke('update_note', {
                         id: currentNote.id,
                         title: currentNote.title,
-                        content: updatedContent
+                        content: updatedContent,
+                        expected_revision: currentNote.revision
                 });
 
                 currentNote = updatedNote;
@@ -372,6 +972,7 @@ async function addSubnote(event) {
 
                 showNotification('Subnote added successfully', 'success');
         } catch (error) {
+                if (await resolveConflict(error)) return;
                 console.error('Failed to add subnote:', error);
                 showNotification('Failed to add subnote', 'error');
         }
@@ -395,39 +996,112 @@ async function createSubnotesFromCheckboxes(parentId, content) {
         }
 }
 
+// Urutan tampilan: induk dulu, lalu anak-anaknya, beserta posisi di antara saudaranya
+function flattenCheckboxes(checkboxes) {
+        return checkboxes.flatMap((checkbox, index) => [
+                { checkbox, index, siblings: checkboxes.length },
+                ...flattenCheckboxes(checkbox.children ?? []),
+        ]);
+}
+
+// Label status checkbox selain open/done
+const CHECKBOX_STATE_LABELS = {
+        in_progress: 'Sedang dikerjakan',
+        cancelled: 'Dibatalkan',
+        deferred: 'Ditunda',
+        question: 'Pertanyaan',
+        other: 'Lainnya',
+};
+
+// Semua perubahan checkbox menunjuk baris, bukan teks
+async function editCheckbox(parentId, command, args) {
+        try {
+                const updatedNote = await invoke(command, {
+                        note_id: parentId,
+                        expected_revision: currentNote.revision,
+                        ...args,
+                });
+                currentNote = updatedNote;
+                // is_done bisa ikut berubah bila "selesai otomatis" aktif
+                renderSchedule(updatedNote);
+                // Status induk ikut berubah, jadi render ulang seluruh pohon
+                await createSubnotesFromCheckboxes(parentId, updatedNote.content);
+        } catch (error) {
+                if (await resolveConflict(error)) return;
+                console.error('Error updating subnote:', error);
+                showNotification(error.message ?? 'Failed to update subnote', 'error');
+                await createSubnotesFromCheckboxes(parentId, currentNote.content);
+        }
+}
+
 function renderCheckboxes(parentId, checkboxes) {
         const subnoteList = document.getElementById('subnote-list');
         if (!subnoteList) return;
 
         subnoteList.innerHTML = '';
 
-        checkboxes.forEach((checkbox, index) => {
+        flattenCheckboxes(checkboxes).forEach(({ checkbox, index, siblings }) => {
                 const li = document.createElement('li');
-                li.className = `subnote-item${checkbox.completed ? " subnote-item--done" : ""}`;
+                li.className = `subnote-item subnote-item--${checkbox.state}${checkbox.progress === 'checked' ? " subnote-item--done" : ""}`;
+                // Sub-item menjorok sesuai kedalamannya
+                li.style.marginLeft = `${checkbox.depth * 1.5}rem`;
                 li.innerHTML = `
-            <input type="checkbox" id="subnote-${index}" ${checkbox.completed ? "checked" : ""} />
-            <label for="subnote-${index}">${escapeHtml(checkbox.text)}</label>
+            <input type="checkbox" id="subnote-${checkbox.line}" ${checkbox.completed ? "checked" : ""} />
+            <button type="button" class="subnote-state" data-cycle title="${CHECKBOX_STATE_LABELS[checkbox.state] ?? 'Ganti status'}" aria-label="Ganti status">[${escapeHtml(checkbox.mark)}]</button>
+            <label for="subnote-${checkbox.line}" title="Klik dua kali untuk mengubah">${escapeHtml(checkbox.title || checkbox.text)} ${checkboxMetaBadges(checkbox)}</label>
+            <button type="button" class="subnote-action" data-move="-1" aria-label="Naikkan" ${index === 0 ? 'disabled' : ''}>↑</button>
+            <button type="button" class="subnote-action" data-move="1" aria-label="Turunkan" ${index === siblings - 1 ? 'disabled' : ''}>↓</button>
+            <button type="button" class="subnote-action" data-promote aria-label="Jadikan child note" title="Jadikan child note">↗</button>
+            <button type="button" class="subnote-action" data-delete aria-label="Hapus sub-note">✕</button>
         `;
+                li.querySelector('input').indeterminate = checkbox.progress === 'partial';
 
-                const checkboxElement = li.querySelector('input[type="checkbox"]');
-                if (checkboxElement) {
-                        checkboxElement.addEventListener('change', async (event) => {
-                                try {
-                                        const updatedNote = await invoke('update_note_checkbox_status', {
-                                                note_id: parentId,
-                                                checkbox_text: checkbox.text,
-                                                new_status: event.target.checked
-                                        });
-
-                                        currentNote = updatedNote;
-                                        checkbox.completed = event.target.checked;
-                                        li.classList.toggle("subnote-item--done", event.target.checked);
-                                } catch (error) {
-                                        console.error('Error updating subnote status:', error);
-                                        showNotification('Failed to update subnote status', 'error');
-                                }
+                li.querySelector('input').addEventListener('change', (event) => {
+                        editCheckbox(parentId, 'update_note_checkbox_status', {
+                                line: checkbox.line,
+                                new_status: event.target.checked,
                         });
-                }
+                });
+
+                li.querySelector('[data-cycle]').addEventListener('click', () => {
+                        editCheckbox(parentId, 'cycle_note_checkbox', { line: checkbox.line });
+                });
+
+                li.querySelector('label').addEventListener('dblclick', (event) => {
+                        event.preventDefault();
+                        const text = prompt('Edit sub-note:', checkbox.text);
+                        if (!text || text === checkbox.text) return;
+                        editCheckbox(parentId, 'edit_note_checkbox', { line: checkbox.line, text });
+                });
+
+                li.querySelectorAll('[data-move]').forEach(btn => {
+                        btn.addEventListener('click', () => {
+                                editCheckbox(parentId, 'move_note_checkbox', {
+                                        line: checkbox.line,
+                                        index: index + Number(btn.dataset.move),
+                                });
+                        });
+                });
+
+                li.querySelector('[data-promote]').addEventListener('click', async () => {
+                        try {
+                                const child = await invoke('promote_checkbox', {
+                                        note_id: parentId,
+                                        line: checkbox.line,
+                                        expected_revision: currentNote.revision,
+                                });
+                                await loadAllNotes();
+                                await openNoteDetail(child.id);
+                        } catch (error) {
+                                if (await resolveConflict(error)) return;
+                                console.error('Failed to promote checkbox:', error);
+                                showNotification(error.message ?? 'Failed to turn checkbox into a note', 'error');
+                        }
+                });
+
+                li.querySelector('[data-delete]').addEventListener('click', () => {
+                        editCheckbox(parentId, 'delete_note_checkbox', { line: checkbox.line });
+                });
 
                 subnoteList.appendChild(li);
         });
@@ -451,32 +1125,66 @@ function toggleNoteMenu(event) {
 
 async function initTimer() {
         try {
+                await listen('pomodoro://tick', (event) => {
+                        currentState = event.payload;
+                        updateDisplay();
+                });
+                await listen('pomodoro://phase-finished', (event) => {
+                        currentState = event.payload.state;
+                        updateDisplay();
+                        if (!event.payload.was_break && currentState.active_note_id) {
+                                loadAllNotes();
+                        }
+                        showNotification(event.payload.was_break ? 'Break selesai!' : 'Work selesai! Waktunya break.');
+                });
+
                 currentState = await invoke('get_timer_state');
                 updateDisplay();
+                await loadSettings();
         } catch (error) {
                 console.error('Failed to init timer:', error);
         }
 }
 
+async function loadSettings() {
+        const settings = await invoke('get_pomodoro_settings');
+        document.getElementById('work-duration').value = settings.work_minutes;
+        document.getElementById('break-duration').value = settings.break_minutes;
+        document.getElementById('long-break-duration').value = settings.long_break_minutes;
+        document.getElementById('sessions-before-long-break').value = settings.sessions_before_long_break;
+        document.getElementById('auto-check').checked = settings.auto_check;
+        document.getElementById('alarm-enabled').checked = settings.alarm_enabled;
+        document.getElementById('alarm-volume').value = settings.alarm_volume;
+        document.getElementById('alarm-volume-value').textContent = settings.alarm_volume;
+        document.getElementById('alarm-repeat').value = settings.alarm_repeat;
+}
+
 function updateDisplay() {
         if (!currentState) return;
         const pomodoroTime = document.getElementById('pomodoro-time');
         const container = document.querySelector('.pomodoro');
         const workTab = document.querySelector('[data-timer-tab="work"]');
         const breakTab = document.querySelector('[data-timer-tab="break"]');
+        const longBreakTab = document.querySelector('[data-timer-tab="long_break"]');
+        const cycle = document.getElementById('pomodoro-cycle');
         if (!pomodoroTime || !container) return;
 
         pomodoroTime.textContent = formatTime(currentState.remaining);
         if (currentState.is_break) {
                 container.classList.add('pomodoro--break');
                 container.classList.remove('pomodoro--work');
-                breakTab?.classList.add('is-active');
                 workTab?.classList.remove('is-active');
         } else {
                 container.classList.add('pomodoro--work');
                 container.classList.remove('pomodoro--break');
                 workTab?.classList.add('is-active');
-                breakTab?.classList.remove('is-active');
+        }
+        breakTab?.classList.toggle('is-active', currentState.is_break && !currentState.is_long_break);
+        longBreakTab?.classList.toggle('is-active', currentState.is_long_break);
+
+        if (cycle) {
+                const session = Math.min(currentState.completed_sessions + 1, currentState.sessions_before_long_break);
+                cycle.textContent = `Sesi ${session}/${currentState.sessions_before_long_break}`;
         }
         updatePlayButton();
 }
@@ -502,42 +1210,13 @@ async function toggleTimer() {
         if (!currentState) return;
         if (currentState.is_paused) {
                 currentState = await invoke('resume_timer');
-                startTicking();
         } else {
                 currentState = await invoke('pause_timer');
-                stopTicking();
         }
         updateDisplay();
 }
 
-function startTicking() {
-        if (timerInterval) return;
-        timerInterval = setInterval(async () => {
-                currentState = await invoke('tick_timer');
-                updateDisplay();
-                if (currentState.remaining === 0) {
-                        stopTicking();
-                        if (!currentState.is_break && confirm('Work selesai! Mulai break?')) {
-                                currentState = await invoke('start_break');
-                                updateDisplay();
-                        } else if (currentState.is_break) {
-                                alert('Break selesai!');
-                                currentState = await invoke('reset_timer');
-                                updateDisplay();
-                        }
-                }
-        }, 1000);
-}
-
-function stopTicking() {
-        if (timerInterval) {
-                clearInterval(timerInterval);
-                timerInterval = null;
-        }
-}
-
 async function handleReset() {
-        stopTicking();
         currentState = await invoke('reset_timer');
         updateDisplay();
 }
@@ -552,7 +1231,6 @@ function toggleSettings() {
 //         e.preventDefault();
 //         const workMin = parseInt(document.getElementById('work-duration').value);
 //         const breakMin = parseInt(document.getElementById('break-duration').value);
-//         stopTicking();
 //         currentState = await invoke('init_timer', { work_min: workMin, break_min: breakMin });
 //         updateDisplay();
 //         toggleSettings();
@@ -560,23 +1238,35 @@ function toggleSettings() {
 
 async function handleSettingsSubmit(e) {
         e.preventDefault();
-        const workMin = parseInt(document.getElementById('work-duration').value);
-        const breakMin = parseInt(document.getElementById('break-duration').value);
-        
-        stopTicking();
-        
-        // Update duration tanpa reset state
-        currentState = await invoke('update_work_duration', { minutes: workMin });
-        currentState = await invoke('update_break_duration', { minutes: breakMin });
-        
-        updateDisplay();
-        toggleSettings();
+        const settings = {
+                work_minutes: parseInt(document.getElementById('work-duration').value),
+                break_minutes: parseInt(document.getElementById('break-duration').value),
+                long_break_minutes: parseInt(document.getElementById('long-break-duration').value),
+                sessions_before_long_break: parseInt(document.getElementById('sessions-before-long-break').value),
+                auto_check: document.getElementById('auto-check').checked,
+                alarm_enabled: document.getElementById('alarm-enabled').checked,
+                alarm_volume: parseInt(document.getElementById('alarm-volume').value),
+                alarm_repeat: parseInt(document.getElementById('alarm-repeat').value)
+        };
+
+        try {
+                // Durasi yang tidak berubah tidak me-reset timer
+                await invoke('update_pomodoro_settings', { settings });
+                currentState = await invoke('get_timer_state');
+                updateDisplay();
+                toggleSettings();
+        } catch (error) {
+                console.error('Failed to save settings:', error);
+                // Backend errors arrive as { kind, message, context }
+                showNotification(error.message ?? error, 'error');
+        }
 }
 
 async function switchTab(mode) {
-        stopTicking();
-        if (mode === 'break' && !currentState.is_break) {
+        if (mode === 'break' && (!currentState.is_break || currentState.is_long_break)) {
                 currentState = await invoke('start_break');
+        } else if (mode === 'long_break' && !currentState.is_long_break) {
+                currentState = await invoke('start_long_break');
         } else if (mode === 'work' && currentState.is_break) {
                 currentState = await invoke('start_work');
         }